@@ -0,0 +1,182 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+
+use super::archive_index::ArchiveIndex;
+use super::packed_archive::PackedArchive;
+
+// compares the entries two indexes resolve each hash to; build them through `VirtualFileSystem` to
+// compare whole installations with the game's override order applied
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    // same contents, but in a different archive or at a different offset
+    Moved,
+    Changed,
+}
+
+pub struct EntryLocation {
+    pub archive: String,
+    pub offset: usize,
+    pub size: usize,
+}
+
+pub struct ArchiveChange {
+    pub name_hash: u32,
+    pub kind: ChangeKind,
+    pub file_type: &'static str,
+    pub old: Option<EntryLocation>,
+    pub new: Option<EntryLocation>,
+}
+
+impl Display for ChangeKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Moved => "moved",
+            ChangeKind::Changed => "changed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// changes are sorted by file type, then kind, then hash
+pub fn diff(old: &ArchiveIndex, new: &ArchiveIndex) -> std::io::Result<Vec<ArchiveChange>> {
+    let name_hashes: BTreeSet<u32> = old.name_hashes().chain(new.name_hashes()).collect();
+
+    let mut changes = Vec::new();
+    for name_hash in name_hashes {
+        let old_entry = resolve(old, name_hash)?;
+        let new_entry = resolve(new, name_hash)?;
+
+        let (kind, contents) = match (&old_entry, &new_entry) {
+            (None, Some((_, new_contents))) => (ChangeKind::Added, *new_contents),
+            (Some((_, old_contents)), None) => (ChangeKind::Removed, *old_contents),
+            (Some((old_location, old_contents)), Some((new_location, new_contents))) => {
+                if old_contents != new_contents {
+                    (ChangeKind::Changed, *new_contents)
+                } else if old_location.archive != new_location.archive
+                    || old_location.offset != new_location.offset
+                {
+                    (ChangeKind::Moved, *new_contents)
+                } else {
+                    continue;
+                }
+            }
+            (None, None) => continue,
+        };
+
+        changes.push(ArchiveChange {
+            name_hash,
+            kind,
+            file_type: PackedArchive::detect_file_type(contents)?,
+            old: old_entry.map(|(location, _)| location),
+            new: new_entry.map(|(location, _)| location),
+        });
+    }
+
+    changes.sort_by_key(|change| (change.file_type, change.kind, change.name_hash));
+
+    Ok(changes)
+}
+
+fn resolve(
+    index: &ArchiveIndex,
+    name_hash: u32,
+) -> std::io::Result<Option<(EntryLocation, &[u8])>> {
+    let location = match index.locate(name_hash) {
+        Some(location) => location,
+        None => return Ok(None),
+    };
+
+    let archive = &index.archives[location.archive];
+    let entry = &archive.archive_table.entries[location.entry];
+    let contents = archive.contents(entry)?;
+
+    Ok(Some((
+        EntryLocation {
+            archive: archive.name.to_owned(),
+            offset: entry.offset,
+            size: entry.size,
+        },
+        contents,
+    )))
+}
+
+// an index holding one archive named `name`, with `entries` of (hash, contents) laid out back to back
+#[cfg(test)]
+fn test_index(name: &str, entries: &[(u32, &str)]) -> ArchiveIndex {
+    use super::archive_table::{ArchiveTable, ArchiveTableEntry, ALIGNMENT};
+
+    let mut contents = Vec::new();
+    let mut table_entries = Vec::new();
+    for (name_hash, entry_contents) in entries {
+        table_entries.push(ArchiveTableEntry {
+            name_hash: *name_hash,
+            offset: contents.len(),
+            size: entry_contents.len(),
+        });
+        contents.extend_from_slice(entry_contents.as_bytes());
+    }
+
+    let mut map = memmap2::MmapMut::map_anon(contents.len()).unwrap();
+    map.copy_from_slice(&contents);
+
+    let mut index = ArchiveIndex::new();
+    let archive_table = ArchiveTable {
+        le: true,
+        alignment: ALIGNMENT,
+        entries: table_entries,
+    };
+    index.insert_archive(name, archive_table, map.make_read_only().unwrap());
+    index
+}
+
+#[test]
+fn classify_changes() {
+    let old = test_index(
+        "game0",
+        &[(1, "same"), (2, "moves"), (3, "before"), (5, "DDS gone")],
+    );
+    let new = test_index(
+        "game0",
+        &[(1, "same"), (4, "DDS new"), (2, "moves"), (3, "after!")],
+    );
+
+    let changes = diff(&old, &new).unwrap();
+    let summary: Vec<(&str, ChangeKind, u32)> = changes
+        .iter()
+        .map(|change| (change.file_type, change.kind, change.name_hash))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("dds", ChangeKind::Added, 4),
+            ("dds", ChangeKind::Removed, 5),
+            ("unknown", ChangeKind::Moved, 2),
+            ("unknown", ChangeKind::Changed, 3),
+        ]
+    );
+
+    let moved = &changes[2];
+    assert_eq!(moved.old.as_ref().unwrap().offset, 4);
+    assert_eq!(moved.new.as_ref().unwrap().offset, 11);
+    assert!(changes[0].old.is_none());
+    assert!(changes[1].new.is_none());
+}
+
+#[test]
+fn moved_between_archives() {
+    let old = test_index("archives_win64/game0", &[(1, "same")]);
+    let new = test_index("patch_win64/game0", &[(1, "same")]);
+
+    let changes = diff(&old, &new).unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].kind, ChangeKind::Moved);
+    assert_eq!(
+        changes[0].new.as_ref().unwrap().archive,
+        "patch_win64/game0"
+    );
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Cursor, Error, ErrorKind};
+use std::path::Path;
+
+use glob::glob;
+use memmap2::Mmap;
+
+use crate::helpers::error::to_io_error;
+use crate::helpers::serializable::SerializableExt;
+use crate::util::jenkins::hash_string;
+
+use super::archive_table::{ArchiveTable, ArchiveTableEntry};
+use super::file_lists::archive_name;
+
+// only reads the TABs up front and memory-maps the ARCs, so entry contents are paged in as they're
+// used
+
+pub struct ArchiveIndex {
+    pub archives: Vec<IndexedArchive>,
+    lookup: HashMap<u32, Vec<ArchiveLocation>>,
+}
+
+pub struct IndexedArchive {
+    pub name: String,
+    pub archive_table: ArchiveTable,
+    map: Mmap,
+}
+
+#[derive(Clone, Copy)]
+pub struct ArchiveLocation {
+    pub archive: usize,
+    pub entry: usize,
+}
+
+impl ArchiveIndex {
+    pub fn new() -> ArchiveIndex {
+        ArchiveIndex {
+            archives: Vec::new(),
+            lookup: HashMap::new(),
+        }
+    }
+
+    // opens `game_dir/<name>.tab` and `game_dir/<name>.arc`; when several archives contain the same
+    // hash, `locate` returns the one that was added first
+    pub fn add_archive<P: AsRef<Path>>(&mut self, game_dir: &P, name: &str) -> std::io::Result<()> {
+        let base_path = game_dir.as_ref().join(name);
+
+        let archive_table = ArchiveTable::deserialize_from_path(&base_path.with_extension("tab"))?;
+
+        let file = File::open(base_path.with_extension("arc"))?;
+        // safety: the mapping is read-only, and archives aren't expected to change while in use
+        let map = unsafe { Mmap::map(&file)? };
+
+        self.insert_archive(name, archive_table, map);
+        Ok(())
+    }
+
+    // adds an archive whose table and contents are already loaded
+    pub fn insert_archive(&mut self, name: &str, archive_table: ArchiveTable, map: Mmap) {
+        let archive = self.archives.len();
+        for (entry, table_entry) in archive_table.entries.iter().enumerate() {
+            self.lookup
+                .entry(table_entry.name_hash)
+                .or_default()
+                .push(ArchiveLocation { archive, entry });
+        }
+
+        self.archives.push(IndexedArchive {
+            name: name.to_string(),
+            archive_table,
+            map,
+        });
+    }
+
+    // the names of every TAB under `game_dir`, in the form `add_archive` expects
+    pub fn find_archives<P: AsRef<Path>>(game_dir: &P) -> std::io::Result<Vec<String>> {
+        let pattern = game_dir.as_ref().join("**").join("*.tab");
+
+        let mut names = Vec::new();
+        for archive_table_path in glob(&pattern.to_string_lossy()).map_err(to_io_error)? {
+            let archive_table_path = archive_table_path.map_err(to_io_error)?;
+            names.push(archive_name(&archive_table_path, game_dir)?);
+        }
+
+        Ok(names)
+    }
+
+    pub fn name_hashes(&self) -> impl Iterator<Item = u32> + '_ {
+        self.lookup.keys().copied()
+    }
+
+    pub fn locate(&self, name_hash: u32) -> Option<ArchiveLocation> {
+        self.locate_all(name_hash).first().copied()
+    }
+
+    // every archive containing `name_hash`, in the order they were added
+    pub fn locate_all(&self, name_hash: u32) -> &[ArchiveLocation] {
+        self.lookup
+            .get(&name_hash)
+            .map(|locations| locations.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn contains(&self, name_hash: u32) -> bool {
+        self.lookup.contains_key(&name_hash)
+    }
+
+    pub fn get(&self, name_hash: u32) -> std::io::Result<&[u8]> {
+        let location = self.locate(name_hash).ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("no entry with name hash {:08X}!", name_hash),
+            )
+        })?;
+
+        let archive = &self.archives[location.archive];
+        archive.contents(&archive.archive_table.entries[location.entry])
+    }
+
+    pub fn open(&self, name_hash: u32) -> std::io::Result<Cursor<&[u8]>> {
+        Ok(Cursor::new(self.get(name_hash)?))
+    }
+
+    pub fn get_by_name(&self, name: &str) -> std::io::Result<&[u8]> {
+        self.get(hash_string(name))
+            .map_err(|err| ArchiveIndex::name_error(err, name))
+    }
+
+    pub fn open_by_name(&self, name: &str) -> std::io::Result<Cursor<&[u8]>> {
+        self.open(hash_string(name))
+            .map_err(|err| ArchiveIndex::name_error(err, name))
+    }
+
+    // reports a missing entry by the name that was asked for rather than its hash
+    fn name_error(err: Error, name: &str) -> Error {
+        match err.kind() {
+            ErrorKind::NotFound => Error::new(ErrorKind::NotFound, format!("{} not found!", name)),
+            _ => err,
+        }
+    }
+}
+
+impl Default for ArchiveIndex {
+    fn default() -> Self {
+        ArchiveIndex::new()
+    }
+}
+
+impl IndexedArchive {
+    pub fn contents(&self, entry: &ArchiveTableEntry) -> std::io::Result<&[u8]> {
+        let end = entry.offset.checked_add(entry.size);
+        match end {
+            Some(end) if end <= self.map.len() => Ok(&self.map[entry.offset..end]),
+            _ => Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!(
+                    "entry {:08X} extends past the end of {}!",
+                    entry.name_hash, self.name
+                ),
+            )),
+        }
+    }
+}
+
+#[test]
+fn lookup_by_name() {
+    let contents = b"intro";
+    let mut map = memmap2::MmapMut::map_anon(contents.len()).unwrap();
+    map.copy_from_slice(contents);
+
+    let archive_table = ArchiveTable {
+        le: true,
+        alignment: super::archive_table::ALIGNMENT,
+        entries: vec![ArchiveTableEntry {
+            name_hash: hash_string("ui/intro.gfx"),
+            offset: 0,
+            size: contents.len(),
+        }],
+    };
+    let mut index = ArchiveIndex::new();
+    index.insert_archive("game0", archive_table, map.make_read_only().unwrap());
+
+    assert_eq!(index.get_by_name("ui/intro.gfx").unwrap(), contents);
+    let missing = index.open_by_name("ui/outro.gfx").err().unwrap();
+    assert_eq!(missing.kind(), ErrorKind::NotFound);
+    assert_eq!(missing.to_string(), "ui/outro.gfx not found!");
+}
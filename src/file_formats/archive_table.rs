@@ -1,13 +1,17 @@
+use std::fmt::{Display, Formatter};
 use std::io::prelude::*;
 use std::io::Error;
 use std::io::ErrorKind;
 
+use crate::helpers::byte::align;
 use crate::helpers::byte::ByteReaderExt;
+use crate::helpers::byte::ByteWriterExt;
 use crate::helpers::byte::StreamLengthExt;
 use crate::helpers::error::to_io_error;
 use crate::helpers::serializable::SerializableExt;
 
 const SIGNATURE: &[u8; 4] = b"TAB\0";
+pub const ALIGNMENT: u32 = 0x800;
 
 pub struct ArchiveTable {
     pub le: bool,
@@ -21,6 +25,136 @@ pub struct ArchiveTableEntry {
     pub size: usize,
 }
 
+pub enum ArchiveIssue {
+    PastEnd {
+        name_hash: u32,
+        end: usize,
+    },
+    Misaligned {
+        name_hash: u32,
+        offset: usize,
+    },
+    Overlap {
+        name_hash: u32,
+        other_name_hash: u32,
+    },
+    DuplicateHash {
+        name_hash: u32,
+        count: usize,
+    },
+    Gap {
+        offset: usize,
+        size: usize,
+    },
+}
+
+impl Display for ArchiveIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArchiveIssue::PastEnd { name_hash, end } => {
+                write!(
+                    f,
+                    "{:08X} ends at {:#X}, past the end of the archive",
+                    name_hash, end
+                )
+            }
+            ArchiveIssue::Misaligned { name_hash, offset } => {
+                write!(
+                    f,
+                    "{:08X} starts at unaligned offset {:#X}",
+                    name_hash, offset
+                )
+            }
+            ArchiveIssue::Overlap {
+                name_hash,
+                other_name_hash,
+            } => write!(f, "{:08X} overlaps {:08X}", name_hash, other_name_hash),
+            ArchiveIssue::DuplicateHash { name_hash, count } => {
+                write!(f, "{:08X} appears {} times", name_hash, count)
+            }
+            ArchiveIssue::Gap { offset, size } => {
+                write!(
+                    f,
+                    "{:#X} bytes at {:#X} are not used by any entry",
+                    size, offset
+                )
+            }
+        }
+    }
+}
+
+impl ArchiveTable {
+    // checks the table against an ARC of `archive_length` bytes; gaps are measured from the end of
+    // each entry's aligned slot, so the padding the alignment requires isn't reported
+    pub fn verify(&self, archive_length: usize) -> Vec<ArchiveIssue> {
+        let mut issues = Vec::new();
+        let alignment = self.alignment as usize;
+
+        let mut entries: Vec<&ArchiveTableEntry> = self.entries.iter().collect();
+        entries.sort_by_key(|entry| entry.name_hash);
+        for group in entries.chunk_by(|a, b| a.name_hash == b.name_hash) {
+            if group.len() > 1 {
+                issues.push(ArchiveIssue::DuplicateHash {
+                    name_hash: group[0].name_hash,
+                    count: group.len(),
+                });
+            }
+        }
+
+        entries.sort_by_key(|entry| (entry.offset, entry.size));
+
+        let mut furthest: Option<&ArchiveTableEntry> = None;
+        let mut used_until = 0;
+        for entry in entries {
+            let end = entry.offset + entry.size;
+
+            if end > archive_length {
+                issues.push(ArchiveIssue::PastEnd {
+                    name_hash: entry.name_hash,
+                    end,
+                });
+            }
+
+            if entry.offset % alignment != 0 {
+                issues.push(ArchiveIssue::Misaligned {
+                    name_hash: entry.name_hash,
+                    offset: entry.offset,
+                });
+            }
+
+            if let Some(other) = furthest {
+                if entry.offset < other.offset + other.size {
+                    issues.push(ArchiveIssue::Overlap {
+                        name_hash: entry.name_hash,
+                        other_name_hash: other.name_hash,
+                    });
+                }
+            }
+
+            if entry.offset > used_until {
+                issues.push(ArchiveIssue::Gap {
+                    offset: used_until,
+                    size: entry.offset - used_until,
+                });
+            }
+
+            if furthest.is_none_or(|other| end > other.offset + other.size) {
+                furthest = Some(entry);
+            }
+            used_until = used_until.max(align(end as u64, alignment as u64) as usize);
+        }
+
+        if archive_length > used_until {
+            issues.push(ArchiveIssue::Gap {
+                offset: used_until,
+                size: archive_length - used_until,
+            });
+        }
+
+        issues
+    }
+}
+
 impl SerializableExt<ArchiveTable> for ArchiveTable {
     fn deserialize<R: Seek + Read>(input: &mut R) -> std::io::Result<ArchiveTable> {
         let le = input.validate_signature(SIGNATURE)?;
@@ -35,7 +169,7 @@ impl SerializableExt<ArchiveTable> for ArchiveTable {
         }
 
         let alignment = input.read_u32(le)?;
-        if alignment != 0x800 {
+        if alignment != ALIGNMENT {
             return Err(Error::new(
                 ErrorKind::InvalidData,
                 "alignment did not match!",
@@ -64,7 +198,78 @@ impl SerializableExt<ArchiveTable> for ArchiveTable {
         })
     }
 
-    fn serialize<R: Seek + Write>(&self, _output: &mut R) -> std::io::Result<()> {
-        unimplemented!()
+    fn serialize<R: Seek + Write>(&self, output: &mut R) -> std::io::Result<()> {
+        let le = self.le;
+
+        if self.alignment != ALIGNMENT {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "alignment did not match!",
+            ));
+        }
+
+        output.write_signature(SIGNATURE, le)?;
+        output.write_u16(2, le)?;
+        output.write_u16(1, le)?;
+        output.write_u32(self.alignment, le)?;
+
+        for entry in &self.entries {
+            output.write_u32(entry.name_hash, le)?;
+            output.write_u32(entry.offset.try_into().map_err(to_io_error)?, le)?;
+            output.write_u32(entry.size.try_into().map_err(to_io_error)?, le)?;
+        }
+
+        Ok(())
     }
 }
+
+#[test]
+fn round_trip() {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"\0BAT");
+    bytes.extend_from_slice(&[0, 2, 0, 1, 0, 0, 8, 0]);
+    bytes.extend_from_slice(&[0x8E, 0x37, 0x2B, 0x3A, 0, 0, 0, 0, 0, 0, 0x01, 0x23]);
+    bytes.extend_from_slice(&[0x12, 0x34, 0x56, 0x78, 0, 0, 0x08, 0, 0, 0, 0, 0x10]);
+
+    let archive_table = ArchiveTable::deserialize_from_bytes(&bytes).unwrap();
+    assert!(!archive_table.le);
+    assert_eq!(archive_table.entries.len(), 2);
+    assert_eq!(archive_table.entries[1].offset, 0x800);
+
+    assert_eq!(archive_table.serialize_to_bytes().unwrap(), bytes);
+}
+
+#[test]
+fn verify_issues() {
+    let entry = |name_hash, offset, size| ArchiveTableEntry {
+        name_hash,
+        offset,
+        size,
+    };
+    let archive_table = ArchiveTable {
+        le: true,
+        alignment: ALIGNMENT,
+        entries: vec![
+            entry(1, 0, 0x10),
+            entry(2, 0x1000, 0x900),
+            entry(2, 0x1810, 0x10),
+            entry(3, 0x2000, 0x10),
+        ],
+    };
+
+    let issues: Vec<String> = archive_table
+        .verify(0x2008)
+        .iter()
+        .map(|issue| issue.to_string())
+        .collect();
+    assert_eq!(
+        issues,
+        [
+            "00000002 appears 2 times",
+            "0x800 bytes at 0x800 are not used by any entry",
+            "00000002 starts at unaligned offset 0x1810",
+            "00000002 overlaps 00000002",
+            "00000003 ends at 0x2010, past the end of the archive",
+        ]
+    );
+}
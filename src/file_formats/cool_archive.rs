@@ -1,12 +1,22 @@
-use std::io::{Cursor, Error, ErrorKind, Read, SeekFrom};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
 
-use flate2::read::ZlibDecoder;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
 
-use crate::helpers::{byte::ByteReaderExt, error::to_io_error, serializable::SerializableExt};
+use crate::helpers::{
+    byte::{align, ByteReaderExt, ByteWriterExt},
+    error::to_io_error,
+    serializable::SerializableExt,
+};
 
 const SIGNATURE: &[u8; 4] = b"AAF\0"; // 0x00464141
 const COMMENT: &[u8; 28] = b"AVALANCHEARCHIVEFORMATISCOOL";
 const CHUNK_SIGNATURE: &[u8; 4] = b"EWAM"; // 0x4D415745
+const CHUNK_HEADER_SIZE: u32 = 16;
+const CHUNK_ALIGNMENT: u64 = 16;
+// large enough that most files fit in a single chunk
+pub const DEFAULT_BLOCK_SIZE: u32 = 0x1000000;
 
 pub struct CoolArchive {
     pub le: bool,
@@ -22,10 +32,31 @@ pub struct CoolArchiveChunk {
     pub contents: Vec<u8>,
 }
 
-impl SerializableExt<CoolArchive> for CoolArchive {
-    fn deserialize<R: std::io::Seek + std::io::Read>(
-        mut input: &mut R,
-    ) -> std::io::Result<CoolArchive> {
+impl CoolArchiveChunk {
+    // prefixes `message` with where chunk `index` starts, for the strict checks
+    fn error(&self, index: u32, message: String) -> Error {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("chunk {} at {:#X}: {}", index, self.data_offset, message),
+        )
+    }
+}
+
+struct Header {
+    le: bool,
+    total_uncompressed_size: u32,
+    block_size: u32,
+    block_count: u32,
+}
+
+struct ChunkHeader {
+    compressed_size: u32,
+    uncompressed_size: u32,
+    next_offset: u32,
+}
+
+impl Header {
+    fn read<R: Read>(input: &mut R) -> std::io::Result<Header> {
         let le = input.validate_signature(SIGNATURE)?;
 
         let version = input.read_u32(le)?;
@@ -38,51 +69,387 @@ impl SerializableExt<CoolArchive> for CoolArchive {
             return Err(Error::new(ErrorKind::InvalidData, "comment did not match!"));
         }
 
-        let total_uncompressed_size = input.read_u32(le)?;
-        let block_size = input.read_u32(le)?;
-        let block_count = input.read_u32(le)?;
+        Ok(Header {
+            le,
+            total_uncompressed_size: input.read_u32(le)?,
+            block_size: input.read_u32(le)?,
+            block_count: input.read_u32(le)?,
+        })
+    }
+}
+
+impl ChunkHeader {
+    fn read<R: Read>(input: &mut R, le: bool) -> std::io::Result<ChunkHeader> {
+        let compressed_size = input.read_u32(le)?;
+        let uncompressed_size = input.read_u32(le)?;
+        let next_offset = input.read_u32(le)?;
+        let block_magic: [u8; 4] = input.read_bytes()?;
+
+        if CHUNK_SIGNATURE != &block_magic {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "chunk signature did not match!",
+            ));
+        }
+
+        Ok(ChunkHeader {
+            compressed_size,
+            uncompressed_size,
+            next_offset,
+        })
+    }
+}
+
+impl SerializableExt<CoolArchive> for CoolArchive {
+    fn deserialize<R: std::io::Seek + std::io::Read>(
+        input: &mut R,
+    ) -> std::io::Result<CoolArchive> {
+        CoolArchive::read(input, false)
+    }
+
+    // the sizes and offsets stored on the archive and its chunks are ignored and recalculated from
+    // the chunk contents
+    fn serialize<R: std::io::Seek + std::io::Write>(&self, output: &mut R) -> std::io::Result<()> {
+        let le = self.le;
+
+        let total_uncompressed_size: usize = self.chunks.iter().map(|c| c.contents.len()).sum();
+
+        output.write_signature(SIGNATURE, le)?;
+        output.write_u32(1, le)?;
+        output.write_all(COMMENT)?;
+        output.write_u32(total_uncompressed_size.try_into().map_err(to_io_error)?, le)?;
+        output.write_u32(self.block_size, le)?;
+        output.write_u32(self.chunks.len().try_into().map_err(to_io_error)?, le)?;
+
+        for chunk in &self.chunks {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&chunk.contents)?;
+            let compressed = encoder.finish()?;
+
+            let compressed_size: u32 = compressed.len().try_into().map_err(to_io_error)?;
+            let chunk_size = CHUNK_HEADER_SIZE as u64 + compressed_size as u64;
+            let next_offset = align(chunk_size, CHUNK_ALIGNMENT);
+
+            output.write_u32(compressed_size, le)?;
+            output.write_u32(chunk.contents.len().try_into().map_err(to_io_error)?, le)?;
+            output.write_u32(next_offset.try_into().map_err(to_io_error)?, le)?;
+            output.write_all(CHUNK_SIGNATURE)?;
+            output.write_all(&compressed)?;
+            output.write_all(&vec![0u8; (next_offset - chunk_size) as usize])?;
+        }
+
+        Ok(())
+    }
+}
+
+impl CoolArchive {
+    // like `deserialize`, but also checks that each chunk's compressed size is exactly what it
+    // inflates from, that the chunks add up to `total_uncompressed_size`, and that only the last
+    // chunk is smaller than `block_size`
+    pub fn deserialize_strict<R: Seek + Read>(input: &mut R) -> std::io::Result<CoolArchive> {
+        CoolArchive::read(input, true)
+    }
+
+    fn read<R: Seek + Read>(mut input: &mut R, strict: bool) -> std::io::Result<CoolArchive> {
+        let header = Header::read(input)?;
+        let le = header.le;
 
         let mut chunks = Vec::new();
-        for _i in 0..block_count {
+        for i in 0..header.block_count {
             let data_offset = input.stream_position()?;
+            let chunk_header = ChunkHeader::read(input, le)?;
+            let mut chunk = CoolArchiveChunk {
+                data_offset,
+                compressed_size: chunk_header.compressed_size,
+                uncompressed_size: chunk_header.uncompressed_size,
+                contents: Vec::new(),
+            };
 
-            let compressed_size = input.read_u32(le)?;
-            let uncompressed_size = input.read_u32(le)?;
-            let next_offset = input.read_u32(le)?;
-            let block_magic: [u8; 4] = input.read_bytes()?;
+            // chunks are raw deflate streams, without a zlib header
+            let mut decoder = DeflateDecoder::new((&mut input).take(chunk.compressed_size.into()));
+            let mut contents = vec![0u8; chunk.uncompressed_size.try_into().map_err(to_io_error)?];
 
-            if CHUNK_SIGNATURE != &block_magic {
+            if strict {
+                decoder
+                    .read_exact(&mut contents)
+                    .map_err(|err| chunk.error(i, err.to_string()))?;
+
+                // anything left over means the sizes in the header are wrong
+                let extra = std::io::copy(&mut decoder, &mut std::io::sink())
+                    .map_err(|err| chunk.error(i, err.to_string()))?;
+                if extra != 0 {
+                    return Err(chunk.error(
+                        i,
+                        format!(
+                            "inflates to {} bytes more than its uncompressed size of {}!",
+                            extra, chunk.uncompressed_size
+                        ),
+                    ));
+                }
+                if decoder.total_in() != chunk.compressed_size as u64 {
+                    return Err(chunk.error(
+                        i,
+                        format!(
+                            "compressed data is {} bytes, but its compressed size is {}!",
+                            decoder.total_in(),
+                            chunk.compressed_size
+                        ),
+                    ));
+                }
+
+                let is_last = i + 1 == header.block_count;
+                if !is_last && chunk.uncompressed_size < header.block_size {
+                    return Err(chunk.error(
+                        i,
+                        format!(
+                            "uncompressed size {} is smaller than the block size of {}!",
+                            chunk.uncompressed_size, header.block_size
+                        ),
+                    ));
+                }
+            } else {
+                decoder.read_exact(&mut contents)?;
+            }
+
+            input.seek(SeekFrom::Start(
+                chunk.data_offset + chunk_header.next_offset as u64,
+            ))?;
+
+            chunk.contents = contents;
+            chunks.push(chunk);
+        }
+
+        let cool_archive = CoolArchive {
+            le,
+            total_uncompressed_size: header.total_uncompressed_size,
+            block_size: header.block_size,
+            chunks,
+        };
+
+        if strict {
+            let total: u64 = cool_archive
+                .chunks
+                .iter()
+                .map(|c| c.uncompressed_size as u64)
+                .sum();
+            if total != cool_archive.total_uncompressed_size as u64 {
                 return Err(Error::new(
                     ErrorKind::InvalidData,
-                    "chunk signature did not match!",
+                    format!(
+                        "chunks add up to {} bytes, but the total uncompressed size is {}!",
+                        total, cool_archive.total_uncompressed_size
+                    ),
                 ));
             }
+        }
 
-            input.seek(SeekFrom::Start(data_offset))?;
+        Ok(cool_archive)
+    }
 
-            let mut decoder = ZlibDecoder::new(&mut input);
-            let mut contents = vec![0u8; uncompressed_size.try_into().map_err(to_io_error)?];
-            decoder.read_exact(&mut contents)?;
+    // splits `contents` into chunks of `block_size` bytes, ready to be serialized
+    pub fn from_contents(contents: &[u8], block_size: u32) -> std::io::Result<CoolArchive> {
+        if block_size == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "block size must not be zero!",
+            ));
+        }
 
-            chunks.push(CoolArchiveChunk {
-                data_offset,
-                compressed_size,
-                uncompressed_size,
-                contents,
+        let chunks = contents
+            .chunks(block_size.try_into().map_err(to_io_error)?)
+            .map(|block| {
+                Ok(CoolArchiveChunk {
+                    data_offset: 0,
+                    compressed_size: 0,
+                    uncompressed_size: block.len().try_into().map_err(to_io_error)?,
+                    contents: block.to_vec(),
+                })
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(CoolArchive {
+            le: true,
+            total_uncompressed_size: contents.len().try_into().map_err(to_io_error)?,
+            block_size,
+            chunks,
+        })
+    }
+}
+
+// reads the decompressed contents of an AAF container, inflating one chunk at a time as it's needed
+pub struct AafReader<R: Read + Seek> {
+    input: R,
+    chunks: Vec<AafReaderChunk>,
+    length: u64,
+    position: u64,
+    current_chunk: Option<(usize, Vec<u8>)>,
+}
+
+struct AafReaderChunk {
+    // where the compressed data starts, just past the chunk header
+    data_offset: u64,
+    compressed_size: u32,
+    uncompressed_offset: u64,
+    uncompressed_size: u32,
+}
+
+impl<R: Read + Seek> AafReader<R> {
+    pub fn new(mut input: R) -> std::io::Result<AafReader<R>> {
+        let header = Header::read(&mut input)?;
+
+        let mut chunks = Vec::new();
+        let mut uncompressed_offset = 0;
+        for _i in 0..header.block_count {
+            let chunk_offset = input.stream_position()?;
+            let chunk_header = ChunkHeader::read(&mut input, header.le)?;
+
+            chunks.push(AafReaderChunk {
+                data_offset: chunk_offset + CHUNK_HEADER_SIZE as u64,
+                compressed_size: chunk_header.compressed_size,
+                uncompressed_offset,
+                uncompressed_size: chunk_header.uncompressed_size,
             });
+            uncompressed_offset += chunk_header.uncompressed_size as u64;
 
-            input.seek(SeekFrom::Start(data_offset + next_offset as u64))?;
+            input.seek(SeekFrom::Start(
+                chunk_offset + chunk_header.next_offset as u64,
+            ))?;
         }
 
-        Ok(CoolArchive {
-            le,
-            total_uncompressed_size,
-            block_size,
+        Ok(AafReader {
+            input,
             chunks,
+            length: uncompressed_offset,
+            position: 0,
+            current_chunk: None,
         })
     }
 
-    fn serialize<R: std::io::Seek + std::io::Write>(&self, _output: &mut R) -> std::io::Result<()> {
-        unimplemented!()
+    fn load_chunk(&mut self, index: usize) -> std::io::Result<&[u8]> {
+        if !matches!(self.current_chunk, Some((current, _)) if current == index) {
+            let chunk = &self.chunks[index];
+            self.input.seek(SeekFrom::Start(chunk.data_offset))?;
+
+            let mut decoder =
+                DeflateDecoder::new((&mut self.input).take(chunk.compressed_size.into()));
+            let mut contents = vec![0u8; chunk.uncompressed_size.try_into().map_err(to_io_error)?];
+            decoder.read_exact(&mut contents)?;
+
+            self.current_chunk = Some((index, contents));
+        }
+
+        match &self.current_chunk {
+            Some((_, contents)) => Ok(contents),
+            None => unreachable!(),
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for AafReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position >= self.length || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let position = self.position;
+        let index = self.chunks.partition_point(|chunk| {
+            chunk.uncompressed_offset + chunk.uncompressed_size as u64 <= position
+        });
+        let start = (position - self.chunks[index].uncompressed_offset) as usize;
+
+        let contents = self.load_chunk(index)?;
+        let read = buf.len().min(contents.len() - start);
+        buf[..read].copy_from_slice(&contents[start..start + read]);
+
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for AafReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative position!",
+            )),
+        }
     }
 }
+
+#[test]
+fn round_trip() {
+    let contents: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+
+    let cool_archive = CoolArchive::from_contents(&contents, 0x8000).unwrap();
+    let bytes = cool_archive.serialize_to_bytes().unwrap();
+
+    let cool_archive = CoolArchive::deserialize_from_bytes(&bytes).unwrap();
+    assert_eq!(cool_archive.total_uncompressed_size, 100_000);
+    assert_eq!(cool_archive.chunks.len(), 4);
+    let chunk_contents: Vec<&[u8]> = cool_archive
+        .chunks
+        .iter()
+        .map(|chunk| chunk.contents.as_slice())
+        .collect();
+    assert_eq!(chunk_contents.concat(), contents);
+}
+
+#[test]
+fn aaf_reader_seek() {
+    let contents: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    let bytes = CoolArchive::from_contents(&contents, 0x8000)
+        .unwrap()
+        .serialize_to_bytes()
+        .unwrap();
+
+    let mut reader = AafReader::new(std::io::Cursor::new(bytes)).unwrap();
+    assert_eq!(reader.seek(SeekFrom::End(0)).unwrap(), 100_000);
+
+    reader.seek(SeekFrom::Start(0x7FF0)).unwrap();
+    let mut straddling = [0u8; 0x20];
+    reader.read_exact(&mut straddling).unwrap();
+    assert_eq!(&straddling, &contents[0x7FF0..0x8010]);
+
+    reader.seek(SeekFrom::Start(0)).unwrap();
+    let mut all = Vec::new();
+    reader.read_to_end(&mut all).unwrap();
+    assert_eq!(all, contents);
+}
+
+#[test]
+fn strict_chunk_sizes() {
+    let contents: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    let bytes = CoolArchive::from_contents(&contents, 0x8000)
+        .unwrap()
+        .serialize_to_bytes()
+        .unwrap();
+    assert!(CoolArchive::deserialize_strict(&mut std::io::Cursor::new(&bytes)).is_ok());
+
+    // the second chunk claims one more compressed byte than it has
+    let second_chunk = 0x30 + u32::from_le_bytes(bytes[0x38..0x3C].try_into().unwrap()) as usize;
+    let mut corrupted = bytes.clone();
+    corrupted[second_chunk] += 1;
+    let err = match CoolArchive::deserialize_strict(&mut std::io::Cursor::new(&corrupted)) {
+        Ok(_) => panic!("corrupted chunk was accepted"),
+        Err(err) => err,
+    };
+    assert!(err
+        .to_string()
+        .starts_with(&format!("chunk 1 at {:#X}:", second_chunk)));
+    assert!(CoolArchive::deserialize_from_bytes(&corrupted).is_ok());
+
+    let mut corrupted = bytes;
+    corrupted[0x24] += 1;
+    assert!(CoolArchive::deserialize_strict(&mut std::io::Cursor::new(&corrupted)).is_err());
+}
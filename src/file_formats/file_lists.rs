@@ -1,6 +1,7 @@
 use glob::glob;
 use std::collections::HashMap;
 use std::fs::read_to_string;
+use std::path::Path;
 
 use crate::helpers::error::to_io_error;
 use crate::util::jenkins::hash_string;
@@ -12,6 +13,23 @@ pub struct FileListEntry {
     pub name_hash: u32,
 }
 
+// the key used in `FileLists`, e.g. `game_dir/archives_win64/game0.tab` becomes `archives_win64/game0`
+pub fn archive_name<P: AsRef<Path>, Q: AsRef<Path>>(
+    path: &P,
+    base_dir: &Q,
+) -> std::io::Result<String> {
+    let name = path
+        .as_ref()
+        .strip_prefix(base_dir)
+        .map_err(to_io_error)?
+        .with_extension("")
+        .iter()
+        .map(|component| component.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    Ok(name)
+}
+
 pub fn load_with_filter(filter: fn(&str) -> bool) -> std::io::Result<FileLists> {
     let mut file_lists: FileLists = HashMap::new();
 
@@ -35,12 +53,7 @@ pub fn load_with_filter(filter: fn(&str) -> bool) -> std::io::Result<FileLists>
             continue;
         }
 
-        let arc_name = path
-            .strip_prefix("file_lists/")
-            .map_err(to_io_error)?
-            .with_extension("")
-            .to_string_lossy()
-            .to_string();
+        let arc_name = archive_name(&path, &"file_lists")?;
 
         file_lists.insert(arc_name, entries);
     }
@@ -51,3 +64,29 @@ pub fn load_with_filter(filter: fn(&str) -> bool) -> std::io::Result<FileLists>
 pub fn load() -> std::io::Result<FileLists> {
     load_with_filter(|_| true)
 }
+
+// groups of different names in `file_lists` that hash to the same value
+pub fn find_collisions(file_lists: &FileLists) -> Vec<(u32, Vec<String>)> {
+    let mut names_by_hash: HashMap<u32, Vec<&str>> = HashMap::new();
+    for entry in file_lists.values().flatten() {
+        let names = names_by_hash.entry(entry.name_hash).or_default();
+        if !names.contains(&entry.name.as_str()) {
+            names.push(&entry.name);
+        }
+    }
+
+    let mut collisions: Vec<(u32, Vec<String>)> = names_by_hash
+        .into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .map(|(name_hash, mut names)| {
+            names.sort_unstable();
+            (
+                name_hash,
+                names.iter().map(|name| name.to_string()).collect(),
+            )
+        })
+        .collect();
+    collisions.sort_unstable_by_key(|(name_hash, _)| *name_hash);
+
+    collisions
+}
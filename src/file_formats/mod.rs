@@ -1,6 +1,10 @@
+pub(crate) mod archive_diff;
+pub(crate) mod archive_index;
 pub(crate) mod archive_table;
 pub(crate) mod cool_archive;
 pub(crate) mod file_lists;
 pub(crate) mod packed_archive;
+pub(crate) mod small_archive;
 pub(crate) mod texture;
 pub(crate) mod texture_serializers;
+pub(crate) mod virtual_file_system;
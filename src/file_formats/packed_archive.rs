@@ -1,12 +1,15 @@
-use std::fs::File;
-use std::io::{BufReader, Read, Seek, SeekFrom};
-use std::path::{Path, PathBuf};
+use std::fs::{copy, read_dir, File};
+use std::io::{BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
 
+use crate::helpers::byte::{align, StreamLengthExt};
 use crate::helpers::error::to_io_error;
 use crate::helpers::serializable::SerializableExt;
+use crate::util::jenkins::hash_string;
 
-use super::archive_table::ArchiveTable;
-use super::file_lists::{FileListEntry, FileLists};
+use super::archive_table::{ArchiveTable, ArchiveTableEntry, ALIGNMENT};
+
+const UNKNOWN_DIRECTORY: &str = "__UNKNOWN";
 
 const SIMPLE_4_LOOKUP: [(u32, &str); 5] = [
     (0x20534444, "dds"),
@@ -24,91 +27,254 @@ const SIMPLE_8_LOOKUP: [(u64, &str); 3] = [
 
 pub struct PackedArchive {}
 
+pub enum ReplaceMode {
+    // copies the `.arc` and `.tab` to `.arc.bak` and `.tab.bak` first, unless a backup exists
+    Backup,
+    // modifies the archive without keeping a copy of the original
+    Overwrite,
+}
+
 pub struct PackedArchiveEntry {
     pub name: String,
     pub contents: Vec<u8>,
 }
 
 impl PackedArchive {
-    pub fn deserialize<R: Seek + Read>(
-        input: &mut R,
-        archive_table: &ArchiveTable,
-        file_list_entries: &mut Vec<FileListEntry>,
-    ) -> std::io::Result<Vec<PackedArchiveEntry>> {
-        let mut entries = Vec::new();
-
-        file_list_entries.sort_unstable_by_key(|entry| entry.name_hash);
+    // e.g. `__UNKNOWN/dds/0123ABCD.dds`, which `name_hash` turns back into the original hash
+    pub fn unknown_entry_name(name_hash: u32, contents: &[u8]) -> std::io::Result<String> {
+        let extension = PackedArchive::detect_file_type(contents)?;
+        Ok(format!(
+            "{}/{}/{:08X}.{}",
+            UNKNOWN_DIRECTORY, extension, name_hash, extension
+        ))
+    }
 
-        for entry in &archive_table.entries {
-            let name = match file_list_entries
-                .binary_search_by_key(&entry.name_hash, |vec_entry| vec_entry.name_hash)
+    pub fn name_hash(name: &str) -> u32 {
+        if let Some(unknown_name) = name
+            .strip_prefix(UNKNOWN_DIRECTORY)
+            .and_then(|name| name.strip_prefix('/'))
+        {
+            let stem = Path::new(unknown_name).file_stem().unwrap_or_default();
+            if let Some(name_hash) = stem
+                .to_str()
+                .filter(|stem| stem.len() == 8)
+                .and_then(|stem| u32::from_str_radix(stem, 16).ok())
             {
-                Ok(index) => file_list_entries[index].name.to_owned(),
-                Err(_err) => continue,
-            };
-
-            input.seek(SeekFrom::Start(
-                entry.offset.try_into().map_err(to_io_error)?,
-            ))?;
-            let mut contents = vec![0u8; entry.size];
-            input.read(&mut contents)?;
-
-            entries.push(PackedArchiveEntry {
-                name: name.to_owned(),
-                contents,
-            })
+                return name_hash;
+            }
         }
 
-        Ok(entries)
+        hash_string(name)
+    }
+
+    // entries are written in the order given, each starting on an aligned boundary, while the
+    // returned table is sorted by name hash like the ones shipped with the game
+    pub fn serialize<W: Seek + Write>(
+        output: &mut W,
+        entries: &[PackedArchiveEntry],
+        le: bool,
+    ) -> std::io::Result<ArchiveTable> {
+        PackedArchive::check_name_hashes(entries)?;
+
+        let mut table_entries = Vec::new();
+
+        for entry in entries {
+            let offset = output.stream_position()?;
+            output.write_all(&entry.contents)?;
+
+            let end = offset + entry.contents.len() as u64;
+            let padding = align(end, ALIGNMENT.into()) - end;
+            output.write_all(&vec![0u8; padding.try_into().map_err(to_io_error)?])?;
+
+            table_entries.push(ArchiveTableEntry {
+                name_hash: PackedArchive::name_hash(&entry.name),
+                offset: offset.try_into().map_err(to_io_error)?,
+                size: entry.contents.len(),
+            });
+        }
+
+        table_entries.sort_unstable_by_key(|entry| entry.name_hash);
+
+        Ok(ArchiveTable {
+            le,
+            alignment: ALIGNMENT,
+            entries: table_entries,
+        })
     }
 
-    pub fn deserialize_from_path<P: AsRef<Path>>(
+    // checked before anything is written, so a clash doesn't leave a broken archive behind
+    fn check_name_hashes(entries: &[PackedArchiveEntry]) -> std::io::Result<()> {
+        let mut name_hashes: Vec<u32> = entries
+            .iter()
+            .map(|entry| PackedArchive::name_hash(&entry.name))
+            .collect();
+        name_hashes.sort_unstable();
+
+        for pair in name_hashes.windows(2) {
+            if pair[0] == pair[1] {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("duplicate name hash {:08X}!", pair[0]),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // writes both halves of the pair, e.g. `mod/game0` becomes `mod/game0.arc` and `mod/game0.tab`
+    pub fn serialize_to_path<P: AsRef<Path>>(
         path: &P,
-        archive_table: &ArchiveTable,
-        file_list_entries: &mut Vec<FileListEntry>,
-    ) -> std::io::Result<Vec<PackedArchiveEntry>> {
-        let file = File::open(path)?;
-        let mut buf_reader = BufReader::new(file);
-        PackedArchive::deserialize(&mut buf_reader, archive_table, file_list_entries)
+        entries: &[PackedArchiveEntry],
+        le: bool,
+    ) -> std::io::Result<ArchiveTable> {
+        // `serialize` checks the entries before writing anything, and the old contents are only cut
+        // off afterwards, so a clash leaves an existing archive untouched
+        let packed_archive_path = path.as_ref().with_extension("arc");
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(packed_archive_path)?;
+        let mut buf_writer = BufWriter::new(file);
+        let archive_table = PackedArchive::serialize(&mut buf_writer, entries, le)?;
+        let end = buf_writer.stream_position()?;
+        buf_writer.flush()?;
+        buf_writer.get_ref().set_len(end)?;
+
+        let archive_table_path = path.as_ref().with_extension("tab");
+        archive_table.serialize_to_path(&archive_table_path)?;
+
+        Ok(archive_table)
     }
 
-    pub fn deserialize_from_file_lists<P: AsRef<Path>>(
-        file_lists: FileLists,
-        game_dir: &P,
+    // names are relative to `directory` and always use forward slashes, matching the file lists
+    pub fn entries_from_directory<P: AsRef<Path>>(
+        directory: &P,
     ) -> std::io::Result<Vec<PackedArchiveEntry>> {
+        let mut paths = Vec::new();
+        let mut pending = vec![directory.as_ref().to_path_buf()];
+        while let Some(path) = pending.pop() {
+            for dir_entry in read_dir(&path)? {
+                let dir_entry = dir_entry?;
+                if dir_entry.file_type()?.is_dir() {
+                    pending.push(dir_entry.path());
+                } else {
+                    paths.push(dir_entry.path());
+                }
+            }
+        }
+        paths.sort();
+
         let mut entries = Vec::new();
+        for path in paths {
+            let name = path
+                .strip_prefix(directory)
+                .map_err(to_io_error)?
+                .iter()
+                .map(|component| component.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            let mut contents = Vec::new();
+            File::open(&path)?.read_to_end(&mut contents)?;
 
-        for (path, mut file_list_entries) in file_lists {
-            let mut base_path = PathBuf::new();
-            base_path.push(game_dir);
-            base_path.push(path);
+            entries.push(PackedArchiveEntry { name, contents });
+        }
+
+        Ok(entries)
+    }
+
+    pub fn replace_entry<P: AsRef<Path>>(
+        path: &P,
+        name: &str,
+        contents: &[u8],
+        mode: ReplaceMode,
+    ) -> std::io::Result<()> {
+        PackedArchive::replace_entry_by_hash(path, PackedArchive::name_hash(name), contents, mode)
+    }
 
-            let archive_table_path = base_path.with_extension("tab");
-            let archive_table = ArchiveTable::deserialize_from_path(&archive_table_path)?;
+    // the new contents are written over the old ones if they fit within the old entry's aligned
+    // slot and no other entry shares it, otherwise they're appended to the end of the archive
+    pub fn replace_entry_by_hash<P: AsRef<Path>>(
+        path: &P,
+        name_hash: u32,
+        contents: &[u8],
+        mode: ReplaceMode,
+    ) -> std::io::Result<()> {
+        let archive_table_path = path.as_ref().with_extension("tab");
+        let packed_archive_path = path.as_ref().with_extension("arc");
 
-            let packed_archive_path = base_path.with_extension("arc");
-            let packed_archive_entries = PackedArchive::deserialize_from_path(
-                &packed_archive_path,
-                &archive_table,
-                &mut file_list_entries,
-            )?;
+        let mut archive_table = ArchiveTable::deserialize_from_path(&archive_table_path)?;
+        let index = archive_table
+            .entries
+            .iter()
+            .position(|entry| entry.name_hash == name_hash)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("no entry with name hash {:08X}!", name_hash),
+                )
+            })?;
 
-            for entry in packed_archive_entries {
-                entries.push(entry);
+        if let ReplaceMode::Backup = mode {
+            for original_path in [&archive_table_path, &packed_archive_path] {
+                let mut backup_path = original_path.clone().into_os_string();
+                backup_path.push(".bak");
+                if !Path::new(&backup_path).exists() {
+                    copy(original_path, &backup_path)?;
+                }
             }
         }
 
-        Ok(entries)
+        let alignment: u64 = archive_table.alignment.into();
+        let entry = &archive_table.entries[index];
+        let slot_start = entry.offset as u64;
+        let slot_end = align(slot_start + entry.size as u64, alignment);
+        let slot_shared = archive_table.entries.iter().enumerate().any(|(i, other)| {
+            let other_start = other.offset as u64;
+            let other_end = other_start + other.size as u64;
+            i != index && other_start < slot_end && other_end > slot_start
+        });
+
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .open(&packed_archive_path)?;
+        let offset = if !slot_shared && slot_start + contents.len() as u64 <= slot_end {
+            slot_start
+        } else {
+            align(file.stream_length()?, alignment)
+        };
+
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(contents)?;
+
+        // clear whatever is left of the old contents, or pad out a newly appended entry
+        let end = offset + contents.len() as u64;
+        let padding = align(end, alignment) - end;
+        file.write_all(&vec![0u8; padding.try_into().map_err(to_io_error)?])?;
+        file.flush()?;
+
+        let entry = &mut archive_table.entries[index];
+        entry.offset = offset.try_into().map_err(to_io_error)?;
+        entry.size = contents.len();
+        archive_table.serialize_to_path(&archive_table_path)
     }
 
-    fn detect_file_extension(guess: &[u8], read: usize) -> std::io::Result<&str> {
+    pub fn detect_file_type(contents: &[u8]) -> std::io::Result<&'static str> {
+        let guess = &contents[..contents.len().min(16)];
+        PackedArchive::detect_file_extension(guess, guess.len())
+    }
+
+    fn detect_file_extension(guess: &[u8], read: usize) -> std::io::Result<&'static str> {
         if read == 0 {
             return Ok("null");
         }
 
         if read >= 4 {
             let magic: [u8; 4] = guess[0..4].try_into().map_err(to_io_error)?;
-            let magic = u32::from_be_bytes(magic);
+            let magic = u32::from_le_bytes(magic);
             for (key, extension) in SIMPLE_4_LOOKUP {
                 if magic == key {
                     return Ok(extension);
@@ -118,7 +284,7 @@ impl PackedArchive {
 
         if read >= 8 {
             let magic: [u8; 8] = guess[0..8].try_into().map_err(to_io_error)?;
-            let magic = u64::from_be_bytes(magic);
+            let magic = u64::from_le_bytes(magic);
             for (key, extension) in SIMPLE_8_LOOKUP {
                 if magic == key {
                     return Ok(extension);
@@ -135,3 +301,167 @@ impl PackedArchive {
         Ok("unknown")
     }
 }
+
+#[test]
+fn serialize_layout() {
+    let entries = vec![
+        PackedArchiveEntry {
+            name: "ui/intro.gfx".to_string(),
+            contents: vec![1u8; 0x801],
+        },
+        PackedArchiveEntry {
+            name: "rico rodriguez :3".to_string(),
+            contents: vec![2u8; 3],
+        },
+    ];
+
+    let mut bytes = Vec::new();
+    let archive_table =
+        PackedArchive::serialize(&mut std::io::Cursor::new(&mut bytes), &entries, true).unwrap();
+
+    assert_eq!(bytes.len(), 0x1800);
+    assert_eq!(archive_table.entries[0].name_hash, 1080157782);
+    assert_eq!(archive_table.entries[0].offset, 0x1000);
+    assert_eq!(archive_table.entries[1].name_hash, 2386027578);
+    assert_eq!(archive_table.entries[1].size, 0x801);
+}
+
+#[test]
+fn serialize_rejects_duplicates() {
+    let entries = vec![
+        PackedArchiveEntry {
+            name: "ui/intro.gfx".to_string(),
+            contents: vec![1u8; 4],
+        },
+        PackedArchiveEntry {
+            name: "ui/intro.gfx".to_string(),
+            contents: vec![2u8; 4],
+        },
+    ];
+
+    let mut bytes = Vec::new();
+    let result = PackedArchive::serialize(&mut std::io::Cursor::new(&mut bytes), &entries, true);
+    assert!(result.is_err());
+    assert!(bytes.is_empty());
+}
+
+#[test]
+fn serialize_to_path_keeps_existing_archive() {
+    let directory = std::env::temp_dir().join("jc3-tools-keeps-existing");
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(directory.join("game0.arc"), b"existing contents").unwrap();
+
+    let mut entries = vec![
+        PackedArchiveEntry {
+            name: "ui/intro.gfx".to_string(),
+            contents: vec![1u8; 4],
+        },
+        PackedArchiveEntry {
+            name: "ui/intro.gfx".to_string(),
+            contents: vec![2u8; 4],
+        },
+    ];
+    assert!(PackedArchive::serialize_to_path(&directory.join("game0"), &entries, true).is_err());
+    assert_eq!(
+        std::fs::read(directory.join("game0.arc")).unwrap(),
+        b"existing contents"
+    );
+
+    // a successful write still replaces the old contents entirely
+    entries.pop();
+    PackedArchive::serialize_to_path(&directory.join("game0"), &entries, true).unwrap();
+    assert_eq!(
+        std::fs::read(directory.join("game0.arc")).unwrap().len(),
+        ALIGNMENT as usize
+    );
+}
+
+#[test]
+fn unknown_name_round_trip() {
+    let name = PackedArchive::unknown_entry_name(0x0123ABCD, b"DDS \0\0\0\0").unwrap();
+    assert_eq!(name, "__UNKNOWN/dds/0123ABCD.dds");
+    assert_eq!(PackedArchive::name_hash(&name), 0x0123ABCD);
+
+    // anything else is hashed like a regular name
+    assert_eq!(
+        PackedArchive::name_hash("__UNKNOWNdds/0123ABCD.dds"),
+        hash_string("__UNKNOWNdds/0123ABCD.dds")
+    );
+}
+
+// packs two small entries into a fresh directory under the system's temporary directory
+#[cfg(test)]
+fn packed_test_archive(test_name: &str) -> std::path::PathBuf {
+    let directory = std::env::temp_dir().join(format!("jc3-tools-{}", test_name));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+
+    let entries = vec![
+        PackedArchiveEntry {
+            name: "a".to_string(),
+            contents: vec![1u8; 4],
+        },
+        PackedArchiveEntry {
+            name: "b".to_string(),
+            contents: vec![2u8; 4],
+        },
+    ];
+    let path = directory.join("game0");
+    PackedArchive::serialize_to_path(&path, &entries, true).unwrap();
+    path
+}
+
+// the offset and size the table has for `name`
+#[cfg(test)]
+fn find_entry(archive_table: &ArchiveTable, name: &str) -> (usize, usize) {
+    let entry = archive_table
+        .entries
+        .iter()
+        .find(|entry| entry.name_hash == hash_string(name))
+        .unwrap();
+    (entry.offset, entry.size)
+}
+
+#[test]
+fn replace_in_place() {
+    let path = packed_test_archive("replace-in-place");
+    let archive_path = path.with_extension("arc");
+
+    PackedArchive::replace_entry(&path, "a", &[3u8; 8], ReplaceMode::Overwrite).unwrap();
+
+    let archive_table = ArchiveTable::deserialize_from_path(&path.with_extension("tab")).unwrap();
+    assert_eq!(find_entry(&archive_table, "a"), (0, 8));
+
+    let bytes = std::fs::read(&archive_path).unwrap();
+    assert_eq!(bytes.len(), 2 * ALIGNMENT as usize);
+    assert_eq!(bytes[..9], [3, 3, 3, 3, 3, 3, 3, 3, 0]);
+    assert!(!Path::new(&format!("{}.bak", archive_path.display())).exists());
+}
+
+#[test]
+fn replace_appends_with_backup() {
+    let path = packed_test_archive("replace-appends");
+    let archive_path = path.with_extension("arc");
+    let table_path = path.with_extension("tab");
+    let original_table = std::fs::read(&table_path).unwrap();
+
+    let contents = vec![4u8; ALIGNMENT as usize + 1];
+    PackedArchive::replace_entry(&path, "a", &contents, ReplaceMode::Backup).unwrap();
+
+    let archive_table = ArchiveTable::deserialize_from_path(&table_path).unwrap();
+    let (offset, size) = find_entry(&archive_table, "a");
+    assert_eq!((offset, size), (2 * ALIGNMENT as usize, contents.len()));
+    assert_eq!(find_entry(&archive_table, "b").0, ALIGNMENT as usize);
+
+    let bytes = std::fs::read(&archive_path).unwrap();
+    assert_eq!(bytes.len(), 4 * ALIGNMENT as usize);
+    assert_eq!(bytes[offset..offset + size], contents);
+    // the old slot is left as it was
+    assert_eq!(bytes[..4], [1, 1, 1, 1]);
+
+    let table_backup = std::fs::read(format!("{}.bak", table_path.display())).unwrap();
+    assert_eq!(table_backup, original_table);
+    let archive_backup = std::fs::read(format!("{}.bak", archive_path.display())).unwrap();
+    assert_eq!(archive_backup.len(), 2 * ALIGNMENT as usize);
+}
@@ -0,0 +1,220 @@
+use std::io::{Cursor, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+
+use crate::helpers::{
+    byte::{align, ByteReaderExt, ByteWriterExt},
+    error::to_io_error,
+    serializable::SerializableExt,
+};
+
+use super::packed_archive::PackedArchiveEntry;
+
+// SARC files are usually found inside AAF containers, e.g. `.ee`, `.bl`, `.nl` and `.fl` files
+
+const SIGNATURE_LENGTH: u32 = 4;
+const SIGNATURE: &[u8; 4] = b"SARC";
+const NAME_ALIGNMENT: u64 = 4;
+const INDEX_ALIGNMENT: u64 = 16;
+const DATA_ALIGNMENT: u64 = 4;
+
+pub struct SmallArchive {
+    pub le: bool,
+    pub entries: Vec<SmallArchiveEntry>,
+}
+
+pub struct SmallArchiveEntry {
+    pub name: String,
+    pub offset: u32,
+    pub size: u32,
+    pub contents: Vec<u8>,
+}
+
+impl SmallArchiveEntry {
+    // entries with no offset refer to a file stored elsewhere, so they have no contents
+    pub fn is_external(&self) -> bool {
+        self.offset == 0
+    }
+}
+
+impl SmallArchive {
+    // the inline entries, named as they are in the archive
+    pub fn into_packed_archive_entries(self) -> Vec<PackedArchiveEntry> {
+        self.entries
+            .into_iter()
+            .filter(|entry| !entry.is_external())
+            .map(|entry| PackedArchiveEntry {
+                name: entry.name,
+                contents: entry.contents,
+            })
+            .collect()
+    }
+
+    // names are always NUL-terminated like in the game's files, so one whose length is already a
+    // multiple of 4 gets a whole word of padding
+    fn name_length(name: &str) -> u64 {
+        align(name.len() as u64 + 1, NAME_ALIGNMENT)
+    }
+}
+
+impl SerializableExt<SmallArchive> for SmallArchive {
+    fn deserialize<R: Seek + Read>(input: &mut R) -> std::io::Result<SmallArchive> {
+        let signature_length: [u8; 4] = input.read_bytes()?;
+        let le = if u32::from_le_bytes(signature_length) == SIGNATURE_LENGTH {
+            true
+        } else if u32::from_be_bytes(signature_length) == SIGNATURE_LENGTH {
+            false
+        } else {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "signature length did not match!",
+            ));
+        };
+
+        let signature: [u8; 4] = input.read_bytes()?;
+        if &signature != SIGNATURE {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "file signature did not match!",
+            ));
+        }
+
+        let version = input.read_u32(le)?;
+        if version != 2 {
+            return Err(Error::new(ErrorKind::InvalidData, "version did not match!"));
+        }
+
+        let index_size = input.read_u32(le)?;
+        let mut index_bytes = vec![0u8; index_size.try_into().map_err(to_io_error)?];
+        input.read_exact(&mut index_bytes)?;
+
+        // the index is padded, and anything shorter than an entry with a name is padding
+        let mut index = Cursor::new(index_bytes);
+        let mut entries = Vec::new();
+        while index_size as u64 - index.position() > 15 {
+            let name_length = index.read_u32(le)?;
+            let name = index.read_string(name_length)?;
+            let offset = index.read_u32(le)?;
+            let size = index.read_u32(le)?;
+
+            entries.push(SmallArchiveEntry {
+                name: name.trim_end_matches('\0').to_string(),
+                offset,
+                size,
+                contents: Vec::new(),
+            });
+        }
+
+        for entry in &mut entries {
+            if entry.is_external() {
+                continue;
+            }
+
+            input.seek(SeekFrom::Start(entry.offset.into()))?;
+            entry.contents = vec![0u8; entry.size.try_into().map_err(to_io_error)?];
+            input.read_exact(&mut entry.contents)?;
+        }
+
+        Ok(SmallArchive { le, entries })
+    }
+
+    // offsets and sizes of inline entries are recalculated from their contents, while external
+    // entries are written as they are
+    fn serialize<R: Seek + Write>(&self, output: &mut R) -> std::io::Result<()> {
+        let le = self.le;
+
+        let index_size: u64 = self
+            .entries
+            .iter()
+            .map(|entry| 12 + SmallArchive::name_length(&entry.name))
+            .sum();
+        let index_size = align(index_size, INDEX_ALIGNMENT);
+
+        let mut data_offset = 16 + index_size;
+        let mut index = Vec::new();
+        for entry in &self.entries {
+            let name_length = SmallArchive::name_length(&entry.name);
+            let mut name = entry.name.as_bytes().to_vec();
+            name.resize(name_length.try_into().map_err(to_io_error)?, 0);
+
+            index.write_u32(name_length.try_into().map_err(to_io_error)?, le)?;
+            index.write_all(&name)?;
+
+            if entry.is_external() {
+                index.write_u32(entry.offset, le)?;
+                index.write_u32(entry.size, le)?;
+            } else {
+                data_offset = align(data_offset, DATA_ALIGNMENT);
+                index.write_u32(data_offset.try_into().map_err(to_io_error)?, le)?;
+                index.write_u32(entry.contents.len().try_into().map_err(to_io_error)?, le)?;
+                data_offset += entry.contents.len() as u64;
+            }
+        }
+        index.resize(index_size.try_into().map_err(to_io_error)?, 0);
+
+        output.write_u32(SIGNATURE_LENGTH, le)?;
+        output.write_all(SIGNATURE)?;
+        output.write_u32(2, le)?;
+        output.write_u32(index_size.try_into().map_err(to_io_error)?, le)?;
+        output.write_all(&index)?;
+
+        let mut position = 16 + index_size;
+        for entry in &self.entries {
+            if entry.is_external() {
+                continue;
+            }
+
+            let padding = align(position, DATA_ALIGNMENT) - position;
+            output.write_all(&vec![0u8; padding as usize])?;
+            output.write_all(&entry.contents)?;
+            position += padding + entry.contents.len() as u64;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn round_trip() {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&[4, 0, 0, 0]);
+    bytes.extend_from_slice(b"SARC");
+    bytes.extend_from_slice(&[2, 0, 0, 0, 0x40, 0, 0, 0]);
+    bytes.extend_from_slice(&[8, 0, 0, 0]);
+    bytes.extend_from_slice(b"a/b.txt\0");
+    bytes.extend_from_slice(&[0x50, 0, 0, 0, 5, 0, 0, 0]);
+    bytes.extend_from_slice(&[12, 0, 0, 0]);
+    bytes.extend_from_slice(b"ext/c.dds\0\0\0");
+    bytes.extend_from_slice(&[0, 0, 0, 0, 0xD2, 0x04, 0, 0]);
+    bytes.extend_from_slice(&[8, 0, 0, 0]);
+    bytes.extend_from_slice(b"d.bin\0\0\0");
+    bytes.extend_from_slice(&[0x58, 0, 0, 0, 3, 0, 0, 0]);
+    bytes.extend_from_slice(b"hello\0\0\0");
+    bytes.extend_from_slice(&[1, 2, 3]);
+
+    let small_archive = SmallArchive::deserialize_from_bytes(&bytes).unwrap();
+    assert_eq!(small_archive.entries.len(), 3);
+    assert_eq!(small_archive.entries[0].contents, b"hello");
+    assert!(small_archive.entries[1].is_external());
+    assert_eq!(small_archive.entries[2].name, "d.bin");
+
+    assert_eq!(small_archive.serialize_to_bytes().unwrap(), bytes);
+}
+
+#[test]
+fn aligned_name() {
+    let small_archive = SmallArchive {
+        le: true,
+        entries: vec![SmallArchiveEntry {
+            name: "abcd.txt".to_string(),
+            offset: 0,
+            size: 0x1234,
+            contents: Vec::new(),
+        }],
+    };
+
+    let bytes = small_archive.serialize_to_bytes().unwrap();
+    assert_eq!(&bytes[16..20], &[12, 0, 0, 0]);
+    assert_eq!(&bytes[20..32], b"abcd.txt\0\0\0\0");
+
+    let small_archive = SmallArchive::deserialize_from_bytes(&bytes).unwrap();
+    assert_eq!(small_archive.entries[0].name, "abcd.txt");
+}
@@ -4,8 +4,16 @@ use crate::helpers::byte::*;
 use crate::helpers::error::to_io_error;
 use crate::helpers::serializable::{SerializableExt, SerializablePartExt};
 
+use super::virtual_file_system::VirtualFileSystem;
+
 const SIGNATURE: &[u8; 4] = b"AVTX"; // 0x58545641
 const ELEMENT_COUNT: usize = 8;
+const HEADER_SIZE: u64 = 0x80;
+const ELEMENT_ALIGNMENT: u64 = 16;
+const CUBE_MAP_FLAG: u16 = 0x40;
+const VOLUME_DIMENSION: u8 = 3;
+// a 65535-wide texture has 16 mips, so any more can only come from a corrupt header
+const MAX_MIP_COUNT: usize = 16;
 
 pub struct Texture {
     pub le: bool,
@@ -18,10 +26,87 @@ pub struct Texture {
     pub flags: u16,
     pub mip_count: u8,
     pub header_mip_count: u8,
+    pub unknown_16: u8,
+    pub unknown_17: u8,
+    pub unknown_18: u8,
+    pub unknown_19: u8,
+    pub unknown_1a: u8,
+    pub unknown_1b: u8,
     pub unknown_1c: u32,
     pub elements: Vec<TextureElement>,
 }
 
+// what `unknown_16` and `unknown_18` mean hasn't been pinned down yet, so these only name the
+// values that have been seen in the game's files; anything else is kept as-is
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextureCategory {
+    Category0,
+    Category1,
+    Category2,
+    Unknown(u8),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextureUsage {
+    Usage0,
+    Usage1,
+    Usage2,
+    Usage3,
+    Usage4,
+    Unknown(u8),
+}
+
+impl From<u8> for TextureCategory {
+    fn from(value: u8) -> TextureCategory {
+        match value {
+            0 => TextureCategory::Category0,
+            1 => TextureCategory::Category1,
+            2 => TextureCategory::Category2,
+            _ => TextureCategory::Unknown(value),
+        }
+    }
+}
+
+impl From<u8> for TextureUsage {
+    fn from(value: u8) -> TextureUsage {
+        match value {
+            0 => TextureUsage::Usage0,
+            1 => TextureUsage::Usage1,
+            2 => TextureUsage::Usage2,
+            3 => TextureUsage::Usage3,
+            4 => TextureUsage::Usage4,
+            _ => TextureUsage::Unknown(value),
+        }
+    }
+}
+
+// a single mip level, borrowed from the element that holds it
+pub struct MipLevel<'a> {
+    pub element: usize,
+    pub index: u8,
+    pub width: u32,
+    pub height: u32,
+    // cube map faces or volume slices, stored one after another in `contents`
+    pub surfaces: u32,
+    pub contents: &'a [u8],
+}
+
+impl<'a> MipLevel<'a> {
+    pub fn surface(&self, index: u32) -> MipLevel<'a> {
+        let size = self.contents.len() / self.surfaces.max(1) as usize;
+        let start = index as usize * size;
+
+        MipLevel {
+            element: self.element,
+            index: self.index,
+            width: self.width,
+            height: self.height,
+            surfaces: 1,
+            contents: &self.contents[start..start + size],
+        }
+    }
+}
+
 pub struct TextureElement {
     pub offset: u32,
     pub size: u32,
@@ -67,6 +152,13 @@ impl SerializableExt<Texture> for Texture {
             elements.push(element);
         }
 
+        if mip_count as usize > MAX_MIP_COUNT {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("mip count {} is too large!", mip_count),
+            ));
+        }
+
         if flags != 0 && (flags & !(1 | 8 | 0x40)) != 0 {
             return Err(Error::new(ErrorKind::InvalidData, "flags did not match!"));
         }
@@ -108,13 +200,367 @@ impl SerializableExt<Texture> for Texture {
             flags,
             mip_count,
             header_mip_count,
+            unknown_16,
+            unknown_17,
+            unknown_18,
+            unknown_19,
+            unknown_1a,
+            unknown_1b,
             unknown_1c,
             elements,
         })
     }
 
-    fn serialize<R: Seek + Write>(&self, _output: &mut R) -> std::io::Result<()> {
-        unimplemented!()
+    // elements are written at the offsets they already have, so call `pack_elements` first if their
+    // contents have changed
+    fn serialize<R: Seek + Write>(&self, output: &mut R) -> std::io::Result<()> {
+        let le = self.le;
+
+        if self.elements.len() != ELEMENT_COUNT {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("textures must have exactly {} elements!", ELEMENT_COUNT),
+            ));
+        }
+
+        output.write_signature(SIGNATURE, le)?;
+        output.write_u16(1, le)?;
+        output.write_u8(self.unknown_06)?;
+        output.write_u8(self.dimension)?;
+        output.write_u32(self.format, le)?;
+        output.write_u16(self.width, le)?;
+        output.write_u16(self.height, le)?;
+        output.write_u16(self.depth, le)?;
+        output.write_u16(self.flags, le)?;
+        output.write_u8(self.mip_count)?;
+        output.write_u8(self.header_mip_count)?;
+        output.write_u8(self.unknown_16)?;
+        output.write_u8(self.unknown_17)?;
+        output.write_u8(self.unknown_18)?;
+        output.write_u8(self.unknown_19)?;
+        output.write_u8(self.unknown_1a)?;
+        output.write_u8(self.unknown_1b)?;
+        output.write_u32(self.unknown_1c, le)?;
+
+        for element in &self.elements {
+            element.write(output, le)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Texture {
+    // picked by `unknown_16`
+    pub fn category(&self) -> TextureCategory {
+        TextureCategory::from(self.unknown_16)
+    }
+
+    // picked by `unknown_18`
+    pub fn usage(&self) -> TextureUsage {
+        TextureUsage::from(self.unknown_18)
+    }
+
+    // `.ddsc` files keep their largest mips in a `.hmddsc` file with the same stem
+    pub fn high_mip_name(name: &str) -> Option<String> {
+        name.strip_suffix(".ddsc")
+            .map(|stem| format!("{}.hmddsc", stem))
+    }
+
+    pub fn has_external_elements(&self) -> bool {
+        self.elements
+            .iter()
+            .any(|element| element.is_external && element.size > 0)
+    }
+
+    // fills in the contents of the external elements from the `.hmddsc` they refer to
+    pub fn read_external_elements<R: Seek + Read>(&mut self, input: &mut R) -> std::io::Result<()> {
+        for element in &mut self.elements {
+            if !element.is_external || element.size == 0 {
+                continue;
+            }
+
+            input.seek(SeekFrom::Start(element.offset.into()))?;
+            element.contents = vec![0u8; element.size.try_into().map_err(to_io_error)?];
+            input.read_exact(&mut element.contents)?;
+        }
+
+        Ok(())
+    }
+
+    // reads `name` and, if it has any external elements, its `.hmddsc`, so every mip is present
+    pub fn load(virtual_file_system: &VirtualFileSystem, name: &str) -> std::io::Result<Texture> {
+        let mut texture = Texture::deserialize(&mut virtual_file_system.open(name)?)?;
+
+        if texture.has_external_elements() {
+            let high_mip_name = Texture::high_mip_name(name).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{} has external elements but isn't a .ddsc file!", name),
+                )
+            })?;
+            texture.read_external_elements(&mut virtual_file_system.open(&high_mip_name)?)?;
+        }
+
+        Ok(texture)
+    }
+
+    // how many bytes one `width` by `height` surface takes up in `format`, or `None` for formats that
+    // aren't understood yet
+    pub fn surface_size(format: u32, width: u32, height: u32) -> Option<usize> {
+        let (width, height) = (width.max(1) as usize, height.max(1) as usize);
+        let blocks = width.div_ceil(4) * height.div_ceil(4);
+
+        match format {
+            // DXGI_FORMAT_BC1_UNORM(_SRGB), DXGI_FORMAT_BC4_UNORM
+            71 | 72 | 80 => Some(8 * blocks),
+            // DXGI_FORMAT_BC2_UNORM(_SRGB), DXGI_FORMAT_BC3_UNORM(_SRGB), DXGI_FORMAT_BC5_UNORM,
+            // DXGI_FORMAT_BC6H_UF16, DXGI_FORMAT_BC6H_SF16, DXGI_FORMAT_BC7_UNORM(_SRGB)
+            74 | 75 | 77 | 78 | 83 | 95 | 96 | 98 | 99 => Some(16 * blocks),
+            // DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_FORMAT_R16G16_UNORM, DXGI_FORMAT_B8G8R8A8_UNORM
+            28 | 35 | 87 => Some(4 * width * height),
+            // DXGI_FORMAT_R16_UNORM
+            56 => Some(2 * width * height),
+            // DXGI_FORMAT_R8_UNORM
+            61 => Some(width * height),
+            _ => None,
+        }
+    }
+
+    pub fn is_block_compressed(format: u32) -> bool {
+        matches!(format, 71..=84 | 94..=99)
+    }
+
+    // cube maps keep all six faces of a mip together, in the order +X, -X, +Y, -Y, +Z, -Z
+    pub fn is_cube_map(&self) -> bool {
+        self.flags & CUBE_MAP_FLAG != 0
+    }
+
+    pub fn is_volume(&self) -> bool {
+        self.dimension == VOLUME_DIMENSION
+    }
+
+    // how many faces or slices mip `index` is made of
+    pub fn surface_count(&self, index: usize) -> u32 {
+        if self.is_cube_map() {
+            6
+        } else if self.is_volume() {
+            (self.depth as u32 >> index).max(1)
+        } else {
+            1
+        }
+    }
+
+    // element `i` starts with mip `i`, and may carry the smaller mips after it; each level is taken
+    // from the first element that has it, and the levels are returned largest first
+    pub fn mip_levels(&self) -> std::io::Result<Vec<MipLevel<'_>>> {
+        let mip_count = self.mip_count.max(1) as usize;
+        let mut levels: Vec<MipLevel> = Vec::new();
+
+        for (element_index, element) in self.elements.iter().enumerate() {
+            let mut start = 0;
+            for index in element_index..mip_count {
+                let width = (self.width as u32 >> index).max(1);
+                let height = (self.height as u32 >> index).max(1);
+                let surfaces = self.surface_count(index);
+                let surface_size =
+                    Texture::surface_size(self.format, width, height).ok_or_else(|| {
+                        Error::new(
+                            ErrorKind::InvalidData,
+                            format!("unrecognized texture format {}!", self.format),
+                        )
+                    })?;
+                let size = surface_size * surfaces as usize;
+
+                if start + size > element.contents.len() {
+                    break;
+                }
+
+                if !levels.iter().any(|level| level.index as usize == index) {
+                    levels.push(MipLevel {
+                        element: element_index,
+                        index: index.try_into().map_err(to_io_error)?,
+                        width,
+                        height,
+                        surfaces,
+                        contents: &element.contents[start..start + size],
+                    });
+                }
+                start += size;
+            }
+        }
+
+        levels.sort_by_key(|level| level.index);
+        Ok(levels)
+    }
+
+    pub fn largest_mip(&self) -> std::io::Result<MipLevel<'_>> {
+        self.mip_levels()?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "texture has no mip levels!"))
+    }
+
+    pub fn mip(&self, index: u8) -> std::io::Result<MipLevel<'_>> {
+        self.mip_levels()?
+            .into_iter()
+            .find(|level| level.index == index)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("mip {} isn't present in the texture!", index),
+                )
+            })
+    }
+
+    // a 2D texture holding `mips`, largest first, with one mip per element and any that don't fit
+    // in the last element; the header fields nothing is known about are left zeroed
+    pub fn from_mips(
+        format: u32,
+        width: u16,
+        height: u16,
+        mips: Vec<Vec<u8>>,
+    ) -> std::io::Result<Texture> {
+        if mips.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "textures need at least one mip!",
+            ));
+        }
+
+        if mips.len() > MAX_MIP_COUNT {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("textures can't have more than {} mips!", MAX_MIP_COUNT),
+            ));
+        }
+
+        for (index, mip) in mips.iter().enumerate() {
+            let mip_width = (width as u32 >> index).max(1);
+            let mip_height = (height as u32 >> index).max(1);
+            if Texture::surface_size(format, mip_width, mip_height) != Some(mip.len()) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "mip {} is {} bytes, which doesn't fit a {}x{} texture in format {}!",
+                        index,
+                        mip.len(),
+                        mip_width,
+                        mip_height,
+                        format
+                    ),
+                ));
+            }
+        }
+
+        let mip_count: u8 = mips.len().try_into().map_err(to_io_error)?;
+        let mut elements: Vec<TextureElement> = Vec::new();
+        for (index, mip) in mips.into_iter().enumerate() {
+            match elements.get_mut(index.min(ELEMENT_COUNT - 1)) {
+                Some(element) => element.contents.extend(mip),
+                None => elements.push(TextureElement {
+                    offset: 0,
+                    size: 0,
+                    unknown_8: 0,
+                    unknown_a: 0,
+                    is_external: false,
+                    contents: mip,
+                }),
+            }
+        }
+        elements.resize_with(ELEMENT_COUNT, || TextureElement {
+            offset: 0,
+            size: 0,
+            unknown_8: 0,
+            unknown_a: 0,
+            is_external: false,
+            contents: Vec::new(),
+        });
+
+        let mut texture = Texture {
+            le: true,
+            unknown_06: 0,
+            dimension: 2,
+            format,
+            width,
+            height,
+            depth: 1,
+            flags: 0,
+            mip_count,
+            header_mip_count: mip_count,
+            unknown_16: 0,
+            unknown_17: 0,
+            unknown_18: 0,
+            unknown_19: 0,
+            unknown_1a: 0,
+            unknown_1b: 0,
+            unknown_1c: 0,
+            elements,
+        };
+        texture.pack_elements()?;
+
+        Ok(texture)
+    }
+
+    // takes the header bytes nothing is known about, and every flag but the cube map one, from the
+    // texture this one replaces, leaving the format, size, shape and mips alone; `original` has to
+    // have as many faces or slices as this one
+    pub fn copy_header_from(&mut self, original: &Texture) -> std::io::Result<()> {
+        if original.surface_count(0) != self.surface_count(0) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "the original texture has {} faces or slices, but the new one has {}!",
+                    original.surface_count(0),
+                    self.surface_count(0)
+                ),
+            ));
+        }
+
+        self.unknown_06 = original.unknown_06;
+        self.flags = (self.flags & CUBE_MAP_FLAG) | (original.flags & !CUBE_MAP_FLAG);
+        self.unknown_16 = original.unknown_16;
+        self.unknown_17 = original.unknown_17;
+        self.unknown_18 = original.unknown_18;
+        self.unknown_19 = original.unknown_19;
+        self.unknown_1a = original.unknown_1a;
+        self.unknown_1b = original.unknown_1b;
+        self.unknown_1c = original.unknown_1c;
+
+        for (element, original_element) in self.elements.iter_mut().zip(&original.elements) {
+            element.unknown_8 = original_element.unknown_8;
+            element.unknown_a = original_element.unknown_a;
+        }
+
+        Ok(())
+    }
+
+    // lays the contents of the inline elements out one after another following the header, keeping
+    // their current order in the file; external elements are left alone
+    pub fn pack_elements(&mut self) -> std::io::Result<()> {
+        let mut inline: Vec<&mut TextureElement> = self
+            .elements
+            .iter_mut()
+            .filter(|element| !element.is_external && !element.contents.is_empty())
+            .collect();
+        inline.sort_by_key(|element| element.offset);
+
+        let mut offset = HEADER_SIZE;
+        for element in inline {
+            offset = align(offset, ELEMENT_ALIGNMENT);
+            element.offset = offset.try_into().map_err(to_io_error)?;
+            element.size = element.contents.len().try_into().map_err(to_io_error)?;
+            offset += element.contents.len() as u64;
+        }
+
+        for element in &mut self.elements {
+            if !element.is_external && element.contents.is_empty() {
+                element.offset = 0;
+                element.size = 0;
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -126,8 +572,9 @@ impl SerializablePartExt<TextureElement> for TextureElement {
         let unknown_a = input.read_u8()?;
         let is_external = input.read_b8()?;
 
+        // the contents of external elements are read by `Texture::read_external_elements`
         let mut contents = Vec::new();
-        if size > 0 {
+        if size > 0 && !is_external {
             let safe_size: usize = size.try_into().map_err(to_io_error)?;
             contents.resize(safe_size, 0);
             let starting_position = input.stream_position()?;
@@ -159,19 +606,172 @@ impl SerializablePartExt<TextureElement> for TextureElement {
         output.write_u8(self.unknown_a)?;
         output.write_b8(self.is_external)?;
 
-        if self.size > 0 {
-            let starting_position = output.stream_position()?;
-            output.seek(SeekFrom::Start(self.offset.into()))?;
-            let written = output.write(&self.contents)?;
-            if written < self.size.try_into().map_err(to_io_error)? {
+        // the contents of external elements live in the companion `.hmddsc` file
+        if self.size > 0 && !self.is_external {
+            if self.contents.len() != self.size as usize {
                 return Err(Error::new(
                     ErrorKind::InvalidData,
-                    "could not write texture contents!",
+                    "texture contents do not match their size!",
                 ));
             }
+
+            let starting_position = output.stream_position()?;
+            output.seek(SeekFrom::Start(self.offset.into()))?;
+            output.write_all(&self.contents)?;
             output.seek(SeekFrom::Start(starting_position))?;
         }
 
         Ok(())
     }
 }
+
+#[test]
+fn round_trip() {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"AVTX");
+    bytes.extend_from_slice(&[1, 0, 0x11, 2]);
+    bytes.extend_from_slice(&[71, 0, 0, 0]);
+    bytes.extend_from_slice(&[8, 0, 4, 0, 1, 0, 8, 0]);
+    bytes.extend_from_slice(&[3, 1, 1, 0, 3, 0, 0, 0]);
+    bytes.extend_from_slice(&[0x78, 0x56, 0x34, 0x12]);
+    // the 8x4 top mip is stored in the `.hmddsc`, followed by elements holding the 4x2 and 2x1 mips
+    // and the 2x1 mip on its own
+    bytes.extend_from_slice(&[0, 0, 0, 0, 16, 0, 0, 0, 0, 0, 0, 1]);
+    bytes.extend_from_slice(&[0x80, 0, 0, 0, 16, 0, 0, 0, 0, 0, 0, 0]);
+    bytes.extend_from_slice(&[0x90, 0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 0]);
+    bytes.resize(0x80, 0);
+    bytes.extend((0..24u8).map(|i| i * 3));
+
+    let texture = Texture::deserialize_from_bytes(&bytes).unwrap();
+    assert_eq!(texture.unknown_16, 1);
+    assert_eq!(texture.unknown_18, 3);
+    assert_eq!(texture.category(), TextureCategory::Category1);
+    assert_eq!(texture.usage(), TextureUsage::Usage3);
+    assert_eq!(texture.elements[1].contents.len(), 16);
+    assert_eq!(texture.serialize_to_bytes().unwrap(), bytes);
+
+    let mut texture = texture;
+    assert!(texture.elements[0].contents.is_empty());
+    let high_mips: Vec<u8> = (100..116).collect();
+    texture
+        .read_external_elements(&mut std::io::Cursor::new(&high_mips))
+        .unwrap();
+    assert_eq!(texture.elements[0].contents, high_mips);
+    assert_eq!(texture.serialize_to_bytes().unwrap(), bytes);
+
+    let levels: Vec<(usize, u8, u32, u32)> = texture
+        .mip_levels()
+        .unwrap()
+        .iter()
+        .map(|level| (level.element, level.index, level.width, level.height))
+        .collect();
+    assert_eq!(levels, [(0, 0, 8, 4), (1, 1, 4, 2), (1, 2, 2, 1)]);
+    assert_eq!(
+        texture.mip(2).unwrap().contents,
+        &texture.elements[1].contents[8..]
+    );
+
+    texture.elements[1].contents.truncate(8);
+    texture.pack_elements().unwrap();
+    assert_eq!(texture.elements[2].offset, 0x90);
+    assert_eq!(texture.elements[1].size, 8);
+}
+
+#[test]
+fn load_high_mips() {
+    use super::file_lists::FileLists;
+    use super::packed_archive::{PackedArchive, PackedArchiveEntry};
+
+    // the 8x4 top mip lives in the `.hmddsc`, the smaller ones inline
+    let mut texture =
+        Texture::from_mips(71, 8, 4, vec![vec![0; 16], vec![0; 8], vec![0; 8]]).unwrap();
+    texture.elements[0].is_external = true;
+    texture.elements[0].offset = 0;
+    texture.pack_elements().unwrap();
+    let texture_bytes = texture.serialize_to_bytes().unwrap();
+    let high_mips: Vec<u8> = (100..116).collect();
+
+    let game_dir = std::env::temp_dir().join("jc3-tools-load-high-mips");
+    let _ = std::fs::remove_dir_all(&game_dir);
+    std::fs::create_dir_all(game_dir.join("archives_win64")).unwrap();
+    let entries = vec![
+        PackedArchiveEntry {
+            name: "textures/a.ddsc".to_string(),
+            contents: texture_bytes.clone(),
+        },
+        PackedArchiveEntry {
+            name: "textures/a.hmddsc".to_string(),
+            contents: high_mips.clone(),
+        },
+        PackedArchiveEntry {
+            name: "textures/b.ddsc".to_string(),
+            contents: texture_bytes,
+        },
+    ];
+    PackedArchive::serialize_to_path(&game_dir.join("archives_win64/game0"), &entries, true)
+        .unwrap();
+    let virtual_file_system =
+        VirtualFileSystem::from_game_dir(&FileLists::new(), &game_dir).unwrap();
+
+    let texture = Texture::load(&virtual_file_system, "textures/a.ddsc").unwrap();
+    assert_eq!(texture.elements[0].contents, high_mips);
+    assert_eq!(
+        texture.largest_mip().unwrap().contents,
+        high_mips.as_slice()
+    );
+
+    let missing = Texture::load(&virtual_file_system, "textures/b.ddsc");
+    assert_eq!(missing.err().unwrap().kind(), ErrorKind::NotFound);
+}
+
+#[test]
+fn copy_header() {
+    let mut original = Texture::from_mips(71, 4, 4, vec![vec![0; 8]]).unwrap();
+    original.unknown_06 = 1;
+    original.unknown_18 = 3;
+    original.unknown_1c = 0x12345678;
+    original.flags = 0x9;
+
+    let mut texture = Texture::from_mips(77, 8, 8, vec![vec![0; 64]]).unwrap();
+    texture.copy_header_from(&original).unwrap();
+    assert_eq!(
+        (texture.unknown_06, texture.unknown_18, texture.unknown_1c),
+        (1, 3, 0x12345678)
+    );
+    assert_eq!(
+        (texture.format, texture.flags, texture.dimension),
+        (77, 0x9, 2)
+    );
+
+    // a 2D texture can't stand in for a cube map
+    original.flags = CUBE_MAP_FLAG | 0x9;
+    assert!(texture.copy_header_from(&original).is_err());
+
+    // but a cube map keeps its shape while taking the other flags
+    let mut cube_map = Texture::from_mips(71, 4, 4, vec![vec![0; 8]]).unwrap();
+    cube_map.flags = CUBE_MAP_FLAG;
+    original.flags = CUBE_MAP_FLAG | 0x8;
+    cube_map.copy_header_from(&original).unwrap();
+    assert_eq!(cube_map.flags, CUBE_MAP_FLAG | 0x8);
+}
+
+#[test]
+fn unknown_category_and_usage() {
+    let mut texture = Texture::from_mips(71, 4, 4, vec![vec![0; 8]]).unwrap();
+    texture.unknown_16 = 7;
+    texture.unknown_18 = 9;
+    assert_eq!(texture.category(), TextureCategory::Unknown(7));
+    assert_eq!(texture.usage(), TextureUsage::Unknown(9));
+}
+
+#[test]
+fn too_many_mips() {
+    let mut texture = Texture::from_mips(71, 4, 4, vec![vec![0; 8]]).unwrap();
+    texture.mip_count = 40;
+    let bytes = texture.serialize_to_bytes().unwrap();
+    let result = Texture::deserialize_from_bytes(&bytes);
+    assert_eq!(result.err().unwrap().kind(), ErrorKind::InvalidData);
+
+    let mips = vec![vec![0; 8]; MAX_MIP_COUNT + 1];
+    assert!(Texture::from_mips(71, 4, 4, mips).is_err());
+}
@@ -0,0 +1,767 @@
+// decoders for the two BPTC formats, BC7 (DXGI 98/99) and BC6H (DXGI 95/96), following the block
+// layouts in https://learn.microsoft.com/en-us/windows/win32/direct3d11/bc7-format-mode-reference
+// and https://learn.microsoft.com/en-us/windows/win32/direct3d11/bc6h-format
+
+// subset of each pixel for the 64 two-subset partitions, one bit per pixel
+const PARTITIONS_2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, 0xC800, 0xFFEC, 0xFE80, 0xE800,
+    0xFFE8, 0xFF00, 0xFFF0, 0xF000, 0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C, 0xAAAA, 0xF0F0, 0x5A5A, 0x33CC,
+    0x3C3C, 0x55AA, 0x9696, 0xA55A, 0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C, 0x9336, 0x9CC6, 0x817E, 0xE718,
+    0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+const PARTITIONS_3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2],
+    [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0],
+    [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0],
+    [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2],
+    [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0],
+    [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0],
+    [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1],
+    [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1],
+    [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2],
+    [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2],
+    [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1],
+    [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+// the pixel whose index has its top bit dropped, for the second subset of two...
+const ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+    2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2,
+    2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+// ...and for the second and third subsets of three
+#[rustfmt::skip]
+const ANCHORS_3: [[u8; 2]; 64] = [
+    [3, 15], [3, 8], [15, 8], [15, 3], [8, 15], [3, 15], [15, 3], [15, 8],
+    [8, 15], [8, 15], [6, 15], [6, 15], [6, 15], [5, 15], [3, 15], [3, 8],
+    [3, 15], [3, 8], [8, 15], [15, 3], [3, 15], [3, 8], [6, 15], [10, 8],
+    [5, 3], [8, 15], [8, 6], [6, 10], [8, 15], [5, 15], [15, 10], [15, 8],
+    [8, 15], [15, 3], [3, 15], [5, 10], [6, 10], [10, 8], [8, 9], [15, 10],
+    [15, 6], [3, 15], [15, 8], [5, 15], [15, 3], [15, 6], [15, 6], [15, 8],
+    [3, 15], [15, 3], [5, 15], [5, 15], [5, 15], [8, 15], [5, 15], [10, 15],
+    [5, 15], [10, 15], [8, 15], [13, 15], [15, 3], [12, 15], [3, 15], [3, 8],
+];
+
+const WEIGHTS_2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS_3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS_4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+// reads a 128-bit block from its least significant bit up
+struct BitReader {
+    bits: u128,
+    position: u32,
+}
+
+impl BitReader {
+    fn new(block: &[u8]) -> BitReader {
+        let mut bytes = [0u8; 16];
+        bytes.copy_from_slice(&block[..16]);
+        BitReader {
+            bits: u128::from_le_bytes(bytes),
+            position: 0,
+        }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+        let value = (self.bits >> self.position) as u32 & (u32::MAX >> (32 - count));
+        self.position += count;
+        value
+    }
+
+    // for the fields BC6H stores most significant bit first
+    fn read_reversed(&mut self, count: u32) -> u32 {
+        (0..count).fold(0, |value, _| (value << 1) | self.read(1))
+    }
+}
+
+fn interpolate(a: u32, b: u32, weight: u32) -> u32 {
+    ((64 - weight) * a + weight * b + 32) >> 6
+}
+
+fn weights(index_bits: u32) -> &'static [u32] {
+    match index_bits {
+        2 => &WEIGHTS_2,
+        3 => &WEIGHTS_3,
+        _ => &WEIGHTS_4,
+    }
+}
+
+fn subset_of(subset_count: usize, partition: usize, pixel: usize) -> usize {
+    match subset_count {
+        2 => (PARTITIONS_2[partition] >> pixel) as usize & 1,
+        3 => PARTITIONS_3[partition][pixel] as usize,
+        _ => 0,
+    }
+}
+
+fn is_anchor(subset_count: usize, partition: usize, pixel: usize) -> bool {
+    pixel == 0
+        || match subset_count {
+            2 => ANCHORS_2[partition] as usize == pixel,
+            3 => ANCHORS_3[partition].contains(&(pixel as u8)),
+            _ => false,
+        }
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_p_bits: bool,
+    shared_p_bits: bool,
+    index_bits: u32,
+    secondary_index_bits: u32,
+}
+
+const fn bc7_mode(fields: [u32; 10]) -> Bc7Mode {
+    Bc7Mode {
+        subsets: fields[0] as usize,
+        partition_bits: fields[1],
+        rotation_bits: fields[2],
+        index_selection_bits: fields[3],
+        color_bits: fields[4],
+        alpha_bits: fields[5],
+        endpoint_p_bits: fields[6] != 0,
+        shared_p_bits: fields[7] != 0,
+        index_bits: fields[8],
+        secondary_index_bits: fields[9],
+    }
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    bc7_mode([3, 4, 0, 0, 4, 0, 1, 0, 3, 0]),
+    bc7_mode([2, 6, 0, 0, 6, 0, 0, 1, 3, 0]),
+    bc7_mode([3, 6, 0, 0, 5, 0, 0, 0, 2, 0]),
+    bc7_mode([2, 6, 0, 0, 7, 0, 1, 0, 2, 0]),
+    bc7_mode([1, 0, 2, 1, 5, 6, 0, 0, 2, 3]),
+    bc7_mode([1, 0, 2, 0, 7, 8, 0, 0, 2, 2]),
+    bc7_mode([1, 0, 0, 0, 7, 7, 1, 0, 4, 0]),
+    bc7_mode([2, 6, 0, 0, 5, 5, 1, 0, 2, 0]),
+];
+
+// widens a `precision`-bit value to eight bits by repeating its top bits
+fn expand(value: u32, precision: u32) -> u32 {
+    let value = value << (8 - precision);
+    value | (value >> precision)
+}
+
+// decodes one 16-byte block to 4x4 RGBA pixels; reserved blocks decode to transparent black
+pub fn decode_bc7_block(block: &[u8]) -> [[u8; 4]; 16] {
+    let mut reader = BitReader::new(block);
+
+    let mode_index = (block[0].trailing_zeros()) as usize;
+    if mode_index >= BC7_MODES.len() {
+        return [[0; 4]; 16];
+    }
+    let mode = &BC7_MODES[mode_index];
+    reader.read(mode_index as u32 + 1);
+
+    let partition = reader.read(mode.partition_bits) as usize;
+    let rotation = reader.read(mode.rotation_bits);
+    let index_selection = reader.read(mode.index_selection_bits);
+
+    // [endpoint][channel], with the endpoints of each subset next to each other
+    let endpoint_count = 2 * mode.subsets;
+    let mut endpoints = [[0u32; 4]; 6];
+    for channel in 0..3 {
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            endpoint[channel] = reader.read(mode.color_bits);
+        }
+    }
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+        endpoint[3] = reader.read(mode.alpha_bits);
+    }
+
+    let mut p_bits = [0u32; 6];
+    if mode.endpoint_p_bits {
+        for p_bit in p_bits.iter_mut().take(endpoint_count) {
+            *p_bit = reader.read(1);
+        }
+    } else if mode.shared_p_bits {
+        for subset in 0..mode.subsets {
+            let p_bit = reader.read(1);
+            p_bits[2 * subset] = p_bit;
+            p_bits[2 * subset + 1] = p_bit;
+        }
+    }
+
+    let has_p_bits = mode.endpoint_p_bits || mode.shared_p_bits;
+    for (endpoint, p_bit) in endpoints.iter_mut().zip(p_bits).take(endpoint_count) {
+        for (channel, value) in endpoint.iter_mut().enumerate() {
+            let bits = if channel < 3 {
+                mode.color_bits
+            } else {
+                mode.alpha_bits
+            };
+            *value = if bits == 0 {
+                255
+            } else if has_p_bits {
+                expand((*value << 1) | p_bit, bits + 1)
+            } else {
+                expand(*value, bits)
+            };
+        }
+    }
+
+    let mut indices = [0u32; 16];
+    for (pixel, index) in indices.iter_mut().enumerate() {
+        let anchor = is_anchor(mode.subsets, partition, pixel);
+        *index = reader.read(mode.index_bits - anchor as u32);
+    }
+    let mut secondary_indices = [0u32; 16];
+    if mode.secondary_index_bits > 0 {
+        for (pixel, index) in secondary_indices.iter_mut().enumerate() {
+            *index = reader.read(mode.secondary_index_bits - (pixel == 0) as u32);
+        }
+    }
+
+    let mut pixels = [[0u8; 4]; 16];
+    for (pixel, output) in pixels.iter_mut().enumerate() {
+        let subset = subset_of(mode.subsets, partition, pixel);
+        let (e0, e1) = (endpoints[2 * subset], endpoints[2 * subset + 1]);
+
+        let (color_index, color_bits, alpha_index, alpha_bits) = if mode.secondary_index_bits == 0 {
+            (
+                indices[pixel],
+                mode.index_bits,
+                indices[pixel],
+                mode.index_bits,
+            )
+        } else if index_selection == 0 {
+            let (primary, secondary) = (mode.index_bits, mode.secondary_index_bits);
+            (indices[pixel], primary, secondary_indices[pixel], secondary)
+        } else {
+            let (primary, secondary) = (mode.index_bits, mode.secondary_index_bits);
+            (secondary_indices[pixel], secondary, indices[pixel], primary)
+        };
+
+        let color_weight = weights(color_bits)[color_index as usize];
+        let alpha_weight = weights(alpha_bits)[alpha_index as usize];
+        let mut rgba = [0u8; 4];
+        for channel in 0..3 {
+            rgba[channel] = interpolate(e0[channel], e1[channel], color_weight) as u8;
+        }
+        rgba[3] = interpolate(e0[3], e1[3], alpha_weight) as u8;
+
+        match rotation {
+            1 => rgba.swap(0, 3),
+            2 => rgba.swap(1, 3),
+            3 => rgba.swap(2, 3),
+            _ => (),
+        }
+        *output = rgba;
+    }
+
+    pixels
+}
+
+// the twelve BC6H endpoint fields: w and x are the first subset's endpoints, y and z the second's
+#[derive(Clone, Copy)]
+enum Field {
+    Rw,
+    Gw,
+    Bw,
+    Rx,
+    Gx,
+    Bx,
+    Ry,
+    Gy,
+    By,
+    Rz,
+    Gz,
+    Bz,
+}
+
+use Field::*;
+
+// `(field, high, low)` in the order they're stored; `low > high` means the bits are reversed
+type Layout = &'static [(Field, u32, u32)];
+
+struct Bc6hMode {
+    value: u32,
+    transformed: bool,
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    layout: Layout,
+}
+
+#[rustfmt::skip]
+const BC6H_MODES: [Bc6hMode; 14] = [
+    Bc6hMode {
+        value: 0x00,
+        transformed: true,
+        endpoint_bits: 10,
+        delta_bits: [5, 5, 5],
+        layout: &[
+            (Gy, 4, 4), (By, 4, 4), (Bz, 4, 4), (Rw, 9, 0), (Gw, 9, 0), (Bw, 9, 0), (Rx, 4, 0),
+            (Gz, 4, 4), (Gy, 3, 0), (Gx, 4, 0), (Bz, 0, 0), (Gz, 3, 0), (Bx, 4, 0), (Bz, 1, 1),
+            (By, 3, 0), (Ry, 4, 0), (Bz, 2, 2), (Rz, 4, 0), (Bz, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        value: 0x01,
+        transformed: true,
+        endpoint_bits: 7,
+        delta_bits: [6, 6, 6],
+        layout: &[
+            (Gy, 5, 5), (Gz, 4, 4), (Gz, 5, 5), (Rw, 6, 0), (Bz, 0, 0), (Bz, 1, 1), (By, 4, 4),
+            (Gw, 6, 0), (By, 5, 5), (Bz, 2, 2), (Gy, 4, 4), (Bw, 6, 0), (Bz, 3, 3), (Bz, 5, 5),
+            (Bz, 4, 4), (Rx, 5, 0), (Gy, 3, 0), (Gx, 5, 0), (Gz, 3, 0), (Bx, 5, 0), (By, 3, 0),
+            (Ry, 5, 0), (Rz, 5, 0),
+        ],
+    },
+    Bc6hMode {
+        value: 0x02,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [5, 4, 4],
+        layout: &[
+            (Rw, 9, 0), (Gw, 9, 0), (Bw, 9, 0), (Rx, 4, 0), (Rw, 10, 10), (Gy, 3, 0), (Gx, 3, 0),
+            (Gw, 10, 10), (Bz, 0, 0), (Gz, 3, 0), (Bx, 3, 0), (Bw, 10, 10), (Bz, 1, 1),
+            (By, 3, 0), (Ry, 4, 0), (Bz, 2, 2), (Rz, 4, 0), (Bz, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        value: 0x06,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 5, 4],
+        layout: &[
+            (Rw, 9, 0), (Gw, 9, 0), (Bw, 9, 0), (Rx, 3, 0), (Rw, 10, 10), (Gz, 4, 4), (Gy, 3, 0),
+            (Gx, 4, 0), (Gw, 10, 10), (Gz, 3, 0), (Bx, 3, 0), (Bw, 10, 10), (Bz, 1, 1),
+            (By, 3, 0), (Ry, 3, 0), (Bz, 0, 0), (Bz, 2, 2), (Rz, 3, 0), (Gy, 4, 4), (Bz, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        value: 0x0A,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 4, 5],
+        layout: &[
+            (Rw, 9, 0), (Gw, 9, 0), (Bw, 9, 0), (Rx, 3, 0), (Rw, 10, 10), (By, 4, 4), (Gy, 3, 0),
+            (Gx, 3, 0), (Gw, 10, 10), (Bz, 0, 0), (Gz, 3, 0), (Bx, 4, 0), (Bw, 10, 10),
+            (By, 3, 0), (Ry, 3, 0), (Bz, 1, 1), (Bz, 2, 2), (Rz, 3, 0), (Bz, 4, 4), (Bz, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        value: 0x0E,
+        transformed: true,
+        endpoint_bits: 9,
+        delta_bits: [5, 5, 5],
+        layout: &[
+            (Rw, 8, 0), (By, 4, 4), (Gw, 8, 0), (Gy, 4, 4), (Bw, 8, 0), (Bz, 4, 4), (Rx, 4, 0),
+            (Gz, 4, 4), (Gy, 3, 0), (Gx, 4, 0), (Bz, 0, 0), (Gz, 3, 0), (Bx, 4, 0), (Bz, 1, 1),
+            (By, 3, 0), (Ry, 4, 0), (Bz, 2, 2), (Rz, 4, 0), (Bz, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        value: 0x12,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [6, 5, 5],
+        layout: &[
+            (Rw, 7, 0), (Gz, 4, 4), (By, 4, 4), (Gw, 7, 0), (Bz, 2, 2), (Gy, 4, 4), (Bw, 7, 0),
+            (Bz, 3, 3), (Bz, 4, 4), (Rx, 5, 0), (Gy, 3, 0), (Gx, 4, 0), (Bz, 0, 0), (Gz, 3, 0),
+            (Bx, 4, 0), (Bz, 1, 1), (By, 3, 0), (Ry, 5, 0), (Rz, 5, 0),
+        ],
+    },
+    Bc6hMode {
+        value: 0x16,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 6, 5],
+        layout: &[
+            (Rw, 7, 0), (Bz, 0, 0), (By, 4, 4), (Gw, 7, 0), (Gy, 5, 5), (Gy, 4, 4), (Bw, 7, 0),
+            (Gz, 5, 5), (Bz, 4, 4), (Rx, 4, 0), (Gz, 4, 4), (Gy, 3, 0), (Gx, 5, 0), (Gz, 3, 0),
+            (Bx, 4, 0), (Bz, 1, 1), (By, 3, 0), (Ry, 4, 0), (Bz, 2, 2), (Rz, 4, 0), (Bz, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        value: 0x1A,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 5, 6],
+        layout: &[
+            (Rw, 7, 0), (Bz, 1, 1), (By, 4, 4), (Gw, 7, 0), (By, 5, 5), (Gy, 4, 4), (Bw, 7, 0),
+            (Bz, 5, 5), (Bz, 4, 4), (Rx, 4, 0), (Gz, 4, 4), (Gy, 3, 0), (Gx, 4, 0), (Bz, 0, 0),
+            (Gz, 3, 0), (Bx, 5, 0), (By, 3, 0), (Ry, 4, 0), (Bz, 2, 2), (Rz, 4, 0), (Bz, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        value: 0x1E,
+        transformed: false,
+        endpoint_bits: 6,
+        delta_bits: [6, 6, 6],
+        layout: &[
+            (Rw, 5, 0), (Gz, 4, 4), (Bz, 0, 0), (Bz, 1, 1), (By, 4, 4), (Gw, 5, 0), (Gy, 5, 5),
+            (By, 5, 5), (Bz, 2, 2), (Gy, 4, 4), (Bw, 5, 0), (Gz, 5, 5), (Bz, 3, 3), (Bz, 5, 5),
+            (Bz, 4, 4), (Rx, 5, 0), (Gy, 3, 0), (Gx, 5, 0), (Gz, 3, 0), (Bx, 5, 0), (By, 3, 0),
+            (Ry, 5, 0), (Rz, 5, 0),
+        ],
+    },
+    Bc6hMode {
+        value: 0x03,
+        transformed: false,
+        endpoint_bits: 10,
+        delta_bits: [10, 10, 10],
+        layout: &[
+            (Rw, 9, 0), (Gw, 9, 0), (Bw, 9, 0), (Rx, 9, 0), (Gx, 9, 0), (Bx, 9, 0),
+        ],
+    },
+    Bc6hMode {
+        value: 0x07,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [9, 9, 9],
+        layout: &[
+            (Rw, 9, 0), (Gw, 9, 0), (Bw, 9, 0), (Rx, 8, 0), (Rw, 10, 10), (Gx, 8, 0),
+            (Gw, 10, 10), (Bx, 8, 0), (Bw, 10, 10),
+        ],
+    },
+    Bc6hMode {
+        value: 0x0B,
+        transformed: true,
+        endpoint_bits: 12,
+        delta_bits: [8, 8, 8],
+        layout: &[
+            (Rw, 9, 0), (Gw, 9, 0), (Bw, 9, 0), (Rx, 7, 0), (Rw, 10, 11), (Gx, 7, 0),
+            (Gw, 10, 11), (Bx, 7, 0), (Bw, 10, 11),
+        ],
+    },
+    Bc6hMode {
+        value: 0x0F,
+        transformed: true,
+        endpoint_bits: 16,
+        delta_bits: [4, 4, 4],
+        layout: &[
+            (Rw, 9, 0), (Gw, 9, 0), (Bw, 9, 0), (Rx, 3, 0), (Rw, 10, 15), (Gx, 3, 0),
+            (Gw, 10, 15), (Bx, 3, 0), (Bw, 10, 15),
+        ],
+    },
+];
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    (value << shift) >> shift
+}
+
+fn unquantize(value: i32, bits: u32, signed: bool) -> i32 {
+    if !signed {
+        if bits >= 15 || value == 0 {
+            value
+        } else if value == (1 << bits) - 1 {
+            0xFFFF
+        } else {
+            ((value << 16) + 0x8000) >> bits
+        }
+    } else if bits >= 16 || value == 0 {
+        value
+    } else {
+        let magnitude = value.abs();
+        let unquantized = if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7FFF
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+        if value < 0 {
+            -unquantized
+        } else {
+            unquantized
+        }
+    }
+}
+
+// the last step of BC6H decoding, which scales an interpolated value to the bits of a half float
+fn finish_unquantize(value: i32, signed: bool) -> u16 {
+    if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        0x8000 | (((-value) * 31) >> 5) as u16
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
+
+pub fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1F) as i32;
+    let mantissa = (half & 0x3FF) as f32;
+
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1F if mantissa == 0.0 => f32::INFINITY,
+        0x1F => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+// decodes one 16-byte block to 4x4 linear RGB pixels; reserved modes decode to black
+pub fn decode_bc6h_block(block: &[u8], signed: bool) -> [[f32; 3]; 16] {
+    let mut reader = BitReader::new(block);
+
+    let mut value = reader.read(2);
+    if value > 1 {
+        value |= reader.read(3) << 2;
+    }
+    let mode = match BC6H_MODES.iter().find(|mode| mode.value == value) {
+        Some(mode) => mode,
+        None => return [[0.0; 3]; 16],
+    };
+
+    let mut fields = [0i32; 12];
+    for &(field, high, low) in mode.layout {
+        if low > high {
+            let bits = reader.read_reversed(low - high + 1);
+            fields[field as usize] |= (bits << high) as i32;
+        } else {
+            fields[field as usize] |= (reader.read(high - low + 1) << low) as i32;
+        }
+    }
+
+    let two_subsets = mode.value & 0x03 != 0x03;
+    let partition = if two_subsets {
+        reader.read(5) as usize
+    } else {
+        0
+    };
+    let subsets = if two_subsets { 2 } else { 1 };
+
+    // [endpoint][channel], in the order w, x, y, z
+    let mut endpoints = [[0i32; 3]; 4];
+    for (endpoint, values) in endpoints.iter_mut().enumerate().take(2 * subsets) {
+        for (channel, value) in values.iter_mut().enumerate() {
+            let raw = fields[3 * endpoint + channel];
+            *value = if endpoint == 0 {
+                if signed {
+                    sign_extend(raw, mode.endpoint_bits)
+                } else {
+                    raw
+                }
+            } else if mode.transformed {
+                let delta = sign_extend(raw, mode.delta_bits[channel]);
+                let value = (fields[channel] + delta) & ((1 << mode.endpoint_bits) - 1);
+                if signed {
+                    sign_extend(value, mode.endpoint_bits)
+                } else {
+                    value
+                }
+            } else if signed {
+                sign_extend(raw, mode.endpoint_bits)
+            } else {
+                raw
+            };
+        }
+    }
+    for values in endpoints.iter_mut() {
+        for value in values.iter_mut() {
+            *value = unquantize(*value, mode.endpoint_bits, signed);
+        }
+    }
+
+    let index_bits = if two_subsets { 3 } else { 4 };
+    let mut pixels = [[0.0f32; 3]; 16];
+    for (pixel, output) in pixels.iter_mut().enumerate() {
+        let anchor = is_anchor(subsets, partition, pixel);
+        let index = reader.read(index_bits - anchor as u32);
+        let weight = weights(index_bits)[index as usize] as i32;
+
+        let subset = subset_of(subsets, partition, pixel);
+        let (e0, e1) = (endpoints[2 * subset], endpoints[2 * subset + 1]);
+        for channel in 0..3 {
+            let value = ((64 - weight) * e0[channel] + weight * e1[channel] + 32) >> 6;
+            output[channel] = half_to_f32(finish_unquantize(value, signed));
+        }
+    }
+
+    pixels
+}
+
+// decodes a whole BC7 surface to 8-bit RGBA
+pub fn decompress_bc7(data: &[u8], width: usize, height: usize, output: &mut [u8]) {
+    decompress_blocks(data, width, height, |block, x, y| {
+        let pixels = decode_bc7_block(block);
+        for (pixel, rgba) in pixels.iter().enumerate() {
+            let (px, py) = (x + pixel % 4, y + pixel / 4);
+            if px < width && py < height {
+                let start = 4 * (py * width + px);
+                output[start..start + 4].copy_from_slice(rgba);
+            }
+        }
+    });
+}
+
+// decodes a whole BC6H surface to linear RGB floats
+pub fn decompress_bc6h(data: &[u8], width: usize, height: usize, signed: bool) -> Vec<f32> {
+    let mut output = vec![0.0f32; 3 * width * height];
+    decompress_blocks(data, width, height, |block, x, y| {
+        let pixels = decode_bc6h_block(block, signed);
+        for (pixel, rgb) in pixels.iter().enumerate() {
+            let (px, py) = (x + pixel % 4, y + pixel / 4);
+            if px < width && py < height {
+                let start = 3 * (py * width + px);
+                output[start..start + 3].copy_from_slice(rgb);
+            }
+        }
+    });
+    output
+}
+
+fn decompress_blocks<F: FnMut(&[u8], usize, usize)>(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    mut decode: F,
+) {
+    let blocks_wide = width.div_ceil(4);
+    for (i, block) in data.chunks_exact(16).enumerate() {
+        let (x, y) = (4 * (i % blocks_wide), 4 * (i / blocks_wide));
+        if y >= height {
+            break;
+        }
+        decode(block, x, y);
+    }
+}
+
+#[test]
+fn tables_are_consistent() {
+    for partition in 0..64 {
+        assert_eq!(
+            subset_of(2, partition, ANCHORS_2[partition] as usize),
+            1,
+            "2-subset partition {}",
+            partition
+        );
+        for (subset, anchor) in ANCHORS_3[partition].iter().enumerate() {
+            assert_eq!(
+                subset_of(3, partition, *anchor as usize),
+                subset + 1,
+                "3-subset partition {}",
+                partition
+            );
+        }
+    }
+
+    // every bit of every endpoint field is stored exactly once
+    for mode in &BC6H_MODES {
+        let mut seen = [0u32; 12];
+        for &(field, high, low) in mode.layout {
+            for bit in high.min(low)..=high.max(low) {
+                assert_eq!(
+                    seen[field as usize] & (1 << bit),
+                    0,
+                    "mode {:#X}",
+                    mode.value
+                );
+                seen[field as usize] |= 1 << bit;
+            }
+        }
+
+        let two_subsets = mode.value & 0x03 != 0x03;
+        for (field, bits) in seen.iter().enumerate() {
+            let expected = match field {
+                0..=2 => mode.endpoint_bits,
+                _ if field >= 6 && !two_subsets => 0,
+                _ => mode.delta_bits[field % 3],
+            };
+            let expected = (1u64 << expected) as u32 - 1;
+            assert_eq!(*bits, expected, "mode {:#X} field {}", mode.value, field);
+        }
+
+        let header_bits: u32 = mode
+            .layout
+            .iter()
+            .map(|(_, high, low)| high.max(low) - high.min(low) + 1)
+            .sum();
+        let mode_bits = if mode.value < 2 { 2 } else { 5 };
+        assert_eq!(header_bits + mode_bits, if two_subsets { 77 } else { 65 });
+    }
+}
+
+#[test]
+fn solid_blocks() {
+    // mode 6 with both endpoints at (0x55, 0xAB, 0xFF, 0x81) once their p-bits are added
+    let mut bits: u128 = 1 << 6;
+    let mut position = 7;
+    let mut push = |value: u128, count: u32| {
+        bits |= value << position;
+        position += count;
+    };
+    for value in [0x2A, 0x2A, 0x55, 0x55, 0x7F, 0x7F, 0x40, 0x40] {
+        push(value, 7);
+    }
+    push(1, 1);
+    push(1, 1);
+    let pixels = decode_bc7_block(&bits.to_le_bytes());
+    assert_eq!(pixels, [[0x55, 0xAB, 0xFF, 0x81]; 16]);
+
+    // mode 11 (0x03) with both endpoints at the largest unsigned value, which is 65504.0
+    let mut bits: u128 = 0x03;
+    for i in 0..6 {
+        bits |= 0x3FF << (5 + 10 * i);
+    }
+    let pixels = decode_bc6h_block(&bits.to_le_bytes(), false);
+    assert_eq!(pixels, [[65504.0; 3]; 16]);
+}
@@ -1,12 +1,17 @@
-use std::io::{Error, ErrorKind, Read, Seek, Write};
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Seek, Write};
+use std::path::Path;
 
 use crate::{
-    file_formats::texture::Texture,
-    helpers::{byte::*, serializable::SerializablePartExt},
+    file_formats::texture::{MipLevel, Texture},
+    helpers::{byte::*, error::to_io_error, serializable::SerializablePartExt},
 };
 
 use super::texture_serializer::TextureSerializerExt;
 
+const DDS_MAGIC: u32 = 0x20534444; // "DDS "
+const DX10_FOUR_CC: u32 = 0x30315844; // "DX10"
+
 pub struct DDSFile {}
 
 impl DDSFile {
@@ -22,14 +27,14 @@ impl DDSFile {
             77 => Ok(PixelFormat::new(FileFormat::DXT5)?),
             // DXGI_FORMAT_B8G8R8A8_UNORM
             87 => Ok(PixelFormat::new(FileFormat::A8R8G8B8)?),
-            // DXGI_FORMAT_R8G8B8A8_UNORM
-            28 => Ok(PixelFormat::new(FileFormat::R8G8B8A8)?),
+            // DXGI_FORMAT_R8G8B8A8_UNORM, which D3D9 calls A8B8G8R8 since its names are little-endian
+            28 => Ok(PixelFormat::new(FileFormat::A8B8G8R8)?),
 
-            // DXGI_FORMAT_R8_UNORM, DXGI_FORMAT_BC5_UNORM, DXGI_FORMAT_BC7_UNORM, DXGI_FORMAT_BC4_UNORM
-            x if x == 61 || x == 83 || x == 98 || x == 80 => Ok(PixelFormat {
+            // formats without a legacy equivalent are described by the DX10 header that follows
+            72 | 75 | 78 | 80 | 83 | 95 | 96 | 98 | 99 | 35 | 56 | 61 => Ok(PixelFormat {
                 size: PixelFormat::DEFAULT_SIZE,
-                flags: 0,
-                four_cc: 0x30315844, // 'DX10'
+                flags: PixelFormatFlags::FourCC as u32,
+                four_cc: DX10_FOUR_CC,
                 rgb_bit_count: 0,
                 red_bit_mask: 0,
                 green_bit_mask: 0,
@@ -42,42 +47,221 @@ impl DDSFile {
                 format!("unrecognized texture format {}!", texture.format),
             )),
         }
+    }
+}
+
+impl DDSFile {
+    // reads a 2D DDS file with legacy or DX10 headers; cube maps, volumes, arrays and formats AVTX
+    // doesn't use are rejected
+    pub fn deserialize<R: Seek + Read>(input: &mut R) -> std::io::Result<Texture> {
+        let le = true;
 
-        // throw new NotSupportedException();
+        if input.read_u32(le)? != DDS_MAGIC {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "file signature did not match!",
+            ));
+        }
+
+        let header = DDSHeader::read(input, le)?;
+        let unsupported =
+            |message: &str| Err(Error::new(ErrorKind::InvalidData, message.to_owned()));
+
+        if header.cubemap_flags != 0 {
+            return unsupported("cube maps are not supported!");
+        }
+        if header.flags & HeaderFlags::Volume as u32 != 0 && header.depth > 1 {
+            return unsupported("volume textures are not supported!");
+        }
+
+        let format = if header.pixel_format.four_cc == DX10_FOUR_CC {
+            let dx10_header = DDSHeaderDX10::read(input, le)?;
+            if dx10_header.resource_dimension != ResourceDimension::Texture2D as u32 {
+                return unsupported("only 2D textures are supported!");
+            }
+            if dx10_header.array_size > 1 || dx10_header.misc_flag != 0 {
+                return unsupported("texture arrays and cube maps are not supported!");
+            }
+            dx10_header.dxgi_format
+        } else {
+            DDSFile::get_dxgi_format(&header.pixel_format)?
+        };
+
+        if Texture::surface_size(format, 1, 1).is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unsupported texture format {}!", format),
+            ));
+        }
+
+        let width: u16 = header.width.try_into().map_err(to_io_error)?;
+        let height: u16 = header.height.try_into().map_err(to_io_error)?;
+        if width == 0 || height == 0 {
+            return unsupported("texture has no pixels!");
+        }
+
+        // a chain can't continue past 1x1
+        let max_mip_count = u16::BITS - width.max(height).leading_zeros();
+        let mip_map_count = if header.flags & HeaderFlags::Mipmap as u32 != 0 {
+            header.mip_map_count.max(1)
+        } else {
+            1
+        };
+        if mip_map_count > max_mip_count {
+            return unsupported("texture has more mips than its size allows!");
+        }
+
+        let mut mips = Vec::new();
+        for index in 0..mip_map_count {
+            let mip_width = (width as u32 >> index).max(1);
+            let mip_height = (height as u32 >> index).max(1);
+            let size = Texture::surface_size(format, mip_width, mip_height).unwrap_or_default();
+
+            let mut contents = vec![0u8; size];
+            input.read_exact(&mut contents)?;
+            mips.push(contents);
+        }
+
+        Texture::from_mips(format, width, height, mips)
+    }
+
+    pub fn deserialize_from_path<P: AsRef<Path>>(path: &P) -> std::io::Result<Texture> {
+        DDSFile::deserialize(&mut BufReader::new(File::open(path)?))
+    }
+
+    // the inverse of `get_pixel_format`, for headers without a DX10 extension
+    fn get_dxgi_format(pixel_format: &PixelFormat) -> std::io::Result<u32> {
+        let masks = (
+            pixel_format.red_bit_mask,
+            pixel_format.green_bit_mask,
+            pixel_format.blue_bit_mask,
+            pixel_format.alpha_bit_mask,
+        );
+
+        if pixel_format.flags & PixelFormatFlags::FourCC as u32 != 0 {
+            match &pixel_format.four_cc.to_le_bytes() {
+                b"DXT1" => Ok(71),
+                b"DXT2" | b"DXT3" => Ok(74),
+                b"DXT4" | b"DXT5" => Ok(77),
+                b"ATI1" | b"BC4U" => Ok(80),
+                b"ATI2" | b"BC5U" => Ok(83),
+                four_cc => Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unsupported FourCC {}!", String::from_utf8_lossy(four_cc)),
+                )),
+            }
+        } else if pixel_format.rgb_bit_count == 32 && masks == (0xFF0000, 0xFF00, 0xFF, 0xFF000000)
+        {
+            // DXGI_FORMAT_B8G8R8A8_UNORM
+            Ok(87)
+        } else if pixel_format.rgb_bit_count == 32 && masks == (0xFF, 0xFF00, 0xFF0000, 0xFF000000)
+        {
+            // DXGI_FORMAT_R8G8B8A8_UNORM
+            Ok(28)
+        } else if pixel_format.rgb_bit_count == 8
+            && pixel_format.flags & PixelFormatFlags::Luminance as u32 != 0
+        {
+            // DXGI_FORMAT_R8_UNORM
+            Ok(61)
+        } else if pixel_format.rgb_bit_count == 16
+            && pixel_format.flags & PixelFormatFlags::Luminance as u32 != 0
+        {
+            // DXGI_FORMAT_R16_UNORM
+            Ok(56)
+        } else {
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                "unsupported pixel format!",
+            ))
+        }
     }
 }
 
 impl TextureSerializerExt for DDSFile {
+    // writes every mip from the largest available one down, as long as none are missing in between
     fn serialize<R: Seek + Write>(output: &mut R, texture: &Texture) -> std::io::Result<()> {
         let le = true;
 
+        let levels = texture.mip_levels()?;
+        let top = levels
+            .first()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "texture has no mip levels!"))?;
+        let chain: Vec<&MipLevel> = levels
+            .iter()
+            .enumerate()
+            .take_while(|(i, level)| level.index as usize == top.index as usize + i)
+            .map(|(_, level)| level)
+            .collect();
+        let mip_map_count: u32 = chain.len().try_into().map_err(to_io_error)?;
+
+        let surface_size = top.surface(0).contents.len();
+        let (size_flag, pitch_or_linear_size) = if Texture::is_block_compressed(texture.format) {
+            (HeaderFlags::LinerSize, surface_size)
+        } else {
+            (HeaderFlags::Pitch, surface_size / top.height as usize)
+        };
+
+        let mut flags = HeaderFlags::Texture as u32 | HeaderFlags::Mipmap as u32 | size_flag as u32;
+        let mut surface_flags = SurfaceFlags::Texture as u32;
+        if mip_map_count > 1 {
+            surface_flags |= SurfaceFlags::Mipmap as u32;
+        }
+
+        let (mut depth, mut cubemap_flags) = (0, 0);
+        let (mut resource_dimension, mut misc_flag) = (ResourceDimension::Texture2D as u32, 0);
+        if texture.is_cube_map() {
+            surface_flags |= SurfaceFlags::Complex as u32;
+            cubemap_flags = CubemapFlags::CubeMap as u32;
+            misc_flag = MiscFlags::TextureCube as u32;
+        } else if texture.is_volume() {
+            flags |= HeaderFlags::Volume as u32;
+            depth = top.surfaces;
+            surface_flags |= SurfaceFlags::Complex as u32;
+            cubemap_flags = CubemapFlags::Volume as u32;
+            resource_dimension = ResourceDimension::Texture3D as u32;
+        }
+
         let header = DDSHeader {
             size: DDSHeader::DEFAULT_SIZE,
-            flags: HeaderFlags::Texture as u32 | HeaderFlags::Mipmap as u32,
-            height: texture.height.into(),
-            width: texture.width.into(),
-            pitch_or_linear_size: 0,
-            depth: 0,
-            mip_map_count: texture.mip_count.into(),
+            flags,
+            height: top.height.try_into().map_err(to_io_error)?,
+            width: top.width.try_into().map_err(to_io_error)?,
+            pitch_or_linear_size: pitch_or_linear_size.try_into().map_err(to_io_error)?,
+            depth,
+            mip_map_count,
             reserved_1: [0u8; 11 * 4],
             pixel_format: DDSFile::get_pixel_format(texture)?,
-            surface_flags: 8,
-            cubemap_flags: 0,
+            surface_flags,
+            cubemap_flags,
             reserved_2: [0u8; 3 * 4],
         };
 
-        output.write_u32(0x20534444, le)?;
+        output.write_u32(DDS_MAGIC, le)?;
         header.write(output, le)?;
 
-        if header.pixel_format.four_cc == 0x3031584 {
-            output.write_u32(texture.format, le)?;
-            output.write_u32(2, le)?;
-            output.write_u32(0, le)?;
-            output.write_u32(1, le)?;
-            output.write_u32(0, le)?;
+        if header.pixel_format.four_cc == DX10_FOUR_CC {
+            let dx10_header = DDSHeaderDX10 {
+                dxgi_format: texture.format,
+                resource_dimension,
+                misc_flag,
+                array_size: 1,
+                misc_flags_2: 0,
+            };
+            dx10_header.write(output, le)?;
         }
 
-        output.write(&texture.elements[0].contents)?;
+        // DDS keeps each cube face's mip chain together, while volumes keep each mip's slices together
+        if texture.is_cube_map() {
+            for face in 0..6 {
+                for level in &chain {
+                    output.write_all(level.surface(face).contents)?;
+                }
+            }
+        } else {
+            for level in chain {
+                output.write_all(level.contents)?;
+            }
+        }
 
         Ok(())
     }
@@ -156,15 +340,69 @@ impl SerializablePartExt<DDSHeader> for DDSHeader {
         output.write_u32(self.pitch_or_linear_size, le)?;
         output.write_u32(self.depth, le)?;
         output.write_u32(self.mip_map_count, le)?;
-        output.write(&self.reserved_1)?;
+        output.write_all(&self.reserved_1)?;
         self.pixel_format.write(output, le)?;
         output.write_u32(self.surface_flags, le)?;
         output.write_u32(self.cubemap_flags, le)?;
-        output.write(&self.reserved_2)?;
+        output.write_all(&self.reserved_2)?;
+        Ok(())
+    }
+}
+
+// DDS_HEADER_DXT10, present when the pixel format's FourCC is 'DX10'
+pub struct DDSHeaderDX10 {
+    pub dxgi_format: u32,
+    pub resource_dimension: u32,
+    pub misc_flag: u32,
+    pub array_size: u32,
+    pub misc_flags_2: u32,
+}
+
+impl SerializablePartExt<DDSHeaderDX10> for DDSHeaderDX10 {
+    fn read<R: Seek + Read>(input: &mut R, le: bool) -> std::io::Result<DDSHeaderDX10> {
+        Ok(DDSHeaderDX10 {
+            dxgi_format: input.read_u32(le)?,
+            resource_dimension: input.read_u32(le)?,
+            misc_flag: input.read_u32(le)?,
+            array_size: input.read_u32(le)?,
+            misc_flags_2: input.read_u32(le)?,
+        })
+    }
+
+    fn write<R: Seek + Write>(&self, output: &mut R, le: bool) -> std::io::Result<()> {
+        output.write_u32(self.dxgi_format, le)?;
+        output.write_u32(self.resource_dimension, le)?;
+        output.write_u32(self.misc_flag, le)?;
+        output.write_u32(self.array_size, le)?;
+        output.write_u32(self.misc_flags_2, le)?;
         Ok(())
     }
 }
 
+#[repr(u32)]
+enum ResourceDimension {
+    Texture2D = 3, // D3D10_RESOURCE_DIMENSION_TEXTURE2D
+    Texture3D = 4, // D3D10_RESOURCE_DIMENSION_TEXTURE3D
+}
+
+#[repr(u32)]
+enum MiscFlags {
+    TextureCube = 0x00000004, // D3D10_RESOURCE_MISC_TEXTURECUBE
+}
+
+#[repr(u32)]
+enum SurfaceFlags {
+    Texture = 0x00001000, // DDSCAPS_TEXTURE
+    Mipmap = 0x00400008,  // DDSCAPS_MIPMAP | DDSCAPS_COMPLEX
+    Complex = 0x00000008, // DDSCAPS_COMPLEX
+}
+
+#[repr(u32)]
+enum CubemapFlags {
+    CubeMap = 0x0000FE00, // DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALLFACES
+    Volume = 0x00200000,  // DDSCAPS2_VOLUME
+}
+
 #[repr(u32)]
 enum HeaderFlags {
     Texture = 0x00001007, // DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT
@@ -376,3 +614,86 @@ pub enum FileFormat {
     R5G6B5,
     INVALID,
 }
+
+#[test]
+fn bc7_mip_chain() {
+    use crate::helpers::serializable::SerializableExt;
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"AVTX");
+    bytes.extend_from_slice(&[1, 0, 0, 2]);
+    bytes.extend_from_slice(&[98, 0, 0, 0]);
+    bytes.extend_from_slice(&[8, 0, 4, 0, 1, 0, 0, 0]);
+    bytes.extend_from_slice(&[3, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    bytes.extend_from_slice(&[0x80, 0, 0, 0, 64, 0, 0, 0, 0, 0, 0, 0]);
+    bytes.resize(0x80, 0);
+    bytes.extend((0..64u8).map(|i| i * 3));
+    let texture = Texture::deserialize_from_bytes(&bytes).unwrap();
+
+    let dds = DDSFile::serialize_to_bytes(&texture).unwrap();
+    let read_u32 = |offset: usize| u32::from_le_bytes(dds[offset..offset + 4].try_into().unwrap());
+    assert_eq!(read_u32(0x1C), 3);
+    assert_eq!(read_u32(0x54), DX10_FOUR_CC);
+    assert_eq!(read_u32(0x80), 98);
+    assert_eq!(read_u32(0x84), 3);
+    assert_eq!(&dds[0x94..], &bytes[0x80..]);
+
+    let imported = DDSFile::deserialize(&mut std::io::Cursor::new(&dds)).unwrap();
+    assert_eq!(imported.format, 98);
+    assert_eq!(
+        (imported.width, imported.height, imported.mip_count),
+        (8, 4, 3)
+    );
+    assert_eq!(
+        imported.mip(2).unwrap().contents,
+        texture.mip(2).unwrap().contents
+    );
+    assert_eq!(DDSFile::serialize_to_bytes(&imported).unwrap(), dds);
+}
+
+#[test]
+fn cube_map_faces() {
+    let mut texture = Texture::from_mips(71, 4, 4, vec![vec![0; 8], vec![0; 8]]).unwrap();
+    texture.flags = 0x40;
+    texture.elements[0].contents = (0..6u8).flat_map(|face| [face; 8]).collect();
+    texture.elements[1].contents = (0..6u8).flat_map(|face| [0x10 | face; 8]).collect();
+    texture.pack_elements().unwrap();
+
+    let dds = DDSFile::serialize_to_bytes(&texture).unwrap();
+    let read_u32 = |offset: usize| u32::from_le_bytes(dds[offset..offset + 4].try_into().unwrap());
+    assert_eq!(read_u32(0x14), 8);
+    assert_eq!(read_u32(0x6C) & 0x8, 0x8);
+    assert_eq!(read_u32(0x70), 0xFE00);
+    assert_eq!(dds.len(), 0x80 + 6 * 16);
+    assert_eq!(dds[0x80..0x90], [[0u8; 8], [0x10; 8]].concat());
+    assert_eq!(dds[0x90..0xA0], [[1u8; 8], [0x11; 8]].concat());
+}
+
+#[test]
+fn format_round_trip() {
+    let formats = [
+        71, 72, 74, 75, 77, 78, 80, 83, 95, 96, 98, 99, 28, 87, 35, 56, 61,
+    ];
+    for format in formats {
+        let mips: Vec<Vec<u8>> = (0..3)
+            .map(|index| {
+                let size = Texture::surface_size(format, 8 >> index, 4 >> index).unwrap();
+                (0..size).map(|i| (i * 7 + index) as u8).collect()
+            })
+            .collect();
+        let texture = Texture::from_mips(format, 8, 4, mips.clone()).unwrap();
+
+        let dds = DDSFile::serialize_to_bytes(&texture).unwrap();
+        let imported = DDSFile::deserialize(&mut std::io::Cursor::new(dds)).unwrap();
+
+        assert_eq!(imported.format, format);
+        assert_eq!((imported.width, imported.height), (8, 4));
+        let imported_mips: Vec<&[u8]> = imported
+            .mip_levels()
+            .unwrap()
+            .iter()
+            .map(|level| level.contents)
+            .collect();
+        assert_eq!(imported_mips, mips, "format {}", format);
+    }
+}
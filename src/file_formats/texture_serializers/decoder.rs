@@ -0,0 +1,311 @@
+use std::io::{Error, ErrorKind};
+
+use texpresso::Format;
+
+use crate::file_formats::texture::{MipLevel, Texture};
+
+use super::bptc::{decompress_bc6h, decompress_bc7};
+
+// how HDR values are brought into 0..1 before they're written to an 8-bit image
+#[derive(Clone, Copy)]
+pub enum ToneMapping {
+    // anything above 1 is clipped
+    Clamp,
+    // x / (1 + x), which keeps some detail in highlights
+    Reinhard,
+}
+
+#[derive(Clone, Copy)]
+pub struct DecodeOptions {
+    pub tone_mapping: ToneMapping,
+    // HDR values are multiplied by this before tone mapping
+    pub exposure: f32,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        DecodeOptions {
+            tone_mapping: ToneMapping::Clamp,
+            exposure: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorSpace {
+    Srgb,
+    // normal maps, masks and the like, which must not be gamma corrected
+    Linear,
+}
+
+impl ColorSpace {
+    // the colour space of the pixels `decode` returns for `texture`
+    pub fn of(texture: &Texture) -> ColorSpace {
+        match texture.format {
+            // the *_SRGB formats, and BC6H, which is sRGB-encoded after tone mapping
+            72 | 75 | 78 | 99 | 95 | 96 => ColorSpace::Srgb,
+            _ => ColorSpace::Linear,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "srgb",
+            ColorSpace::Linear => "linear",
+        }
+    }
+}
+
+// the pixels of one mip level, in the closest layout an image file can hold
+pub enum DecodedImage {
+    Rgba8(Vec<u8>),
+    Gray8(Vec<u8>),
+    // formats with 16-bit channels are kept at 16 bits, so PNGs lose nothing
+    Gray16(Vec<u16>),
+    Rgb16(Vec<u16>),
+}
+
+impl DecodedImage {
+    pub fn into_rgba8(self) -> Vec<u8> {
+        // rounds to the nearest 8-bit value
+        let narrow = |value: u16| ((value as u32 * 255 + 32767) / 65535) as u8;
+
+        match self {
+            DecodedImage::Rgba8(pixels) => pixels,
+            DecodedImage::Gray8(pixels) => pixels.iter().flat_map(|&l| [l, l, l, 0xFF]).collect(),
+            DecodedImage::Gray16(pixels) => pixels
+                .iter()
+                .flat_map(|&l| [narrow(l), narrow(l), narrow(l), 0xFF])
+                .collect(),
+            DecodedImage::Rgb16(pixels) => pixels
+                .chunks_exact(3)
+                .flat_map(|rgb| [narrow(rgb[0]), narrow(rgb[1]), narrow(rgb[2]), 0xFF])
+                .collect(),
+        }
+    }
+
+    // an empty image of the same kind, `width` by `height` pixels
+    fn blank(&self, width: u32, height: u32) -> DecodedImage {
+        let pixels = width as usize * height as usize;
+        match self {
+            DecodedImage::Rgba8(_) => DecodedImage::Rgba8(vec![0; 4 * pixels]),
+            DecodedImage::Gray8(_) => DecodedImage::Gray8(vec![0; pixels]),
+            DecodedImage::Gray16(_) => DecodedImage::Gray16(vec![0; pixels]),
+            DecodedImage::Rgb16(_) => DecodedImage::Rgb16(vec![0; 3 * pixels]),
+        }
+    }
+
+    // copies `source`, which is `width` pixels wide, into this `stride` pixel wide image at `x`, `y`
+    fn paste(&mut self, stride: u32, source: &DecodedImage, width: u32, x: u32, y: u32) {
+        let (stride, width, x, y) = (stride as usize, width as usize, x as usize, y as usize);
+        match (self, source) {
+            (DecodedImage::Rgba8(into), DecodedImage::Rgba8(from)) => {
+                paste(into, stride, from, width, x, y, 4)
+            }
+            (DecodedImage::Gray8(into), DecodedImage::Gray8(from)) => {
+                paste(into, stride, from, width, x, y, 1)
+            }
+            (DecodedImage::Gray16(into), DecodedImage::Gray16(from)) => {
+                paste(into, stride, from, width, x, y, 1)
+            }
+            (DecodedImage::Rgb16(into), DecodedImage::Rgb16(from)) => {
+                paste(into, stride, from, width, x, y, 3)
+            }
+            _ => unreachable!("surfaces of one texture always decode to the same kind of image"),
+        }
+    }
+}
+
+fn paste<T: Copy>(
+    into: &mut [T],
+    stride: usize,
+    from: &[T],
+    width: usize,
+    x: usize,
+    y: usize,
+    channels: usize,
+) {
+    for (row, line) in from.chunks_exact(width * channels).enumerate() {
+        let start = ((y + row) * stride + x) * channels;
+        into[start..start + line.len()].copy_from_slice(line);
+    }
+}
+
+// where each cube map face goes in a 4x3 horizontal cross, in columns and rows
+const CUBE_CROSS: [(u32, u32); 6] = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)];
+
+// decodes every surface of `mip` into one image, returned with its width and height; cube map faces
+// are laid out as a horizontal cross, and volume slices are stacked top to bottom
+pub fn decode_surfaces(
+    texture: &Texture,
+    mip: &MipLevel,
+    options: &DecodeOptions,
+) -> std::io::Result<(DecodedImage, u32, u32)> {
+    if mip.surfaces <= 1 {
+        return Ok((decode(texture, mip, options)?, mip.width, mip.height));
+    }
+
+    let positions: Vec<(u32, u32)> = if texture.is_cube_map() {
+        CUBE_CROSS.to_vec()
+    } else {
+        (0..mip.surfaces).map(|slice| (0, slice)).collect()
+    };
+    let columns = positions
+        .iter()
+        .map(|(column, _)| column + 1)
+        .max()
+        .unwrap_or(1);
+    let rows = positions.iter().map(|(_, row)| row + 1).max().unwrap_or(1);
+    let (width, height) = (columns * mip.width, rows * mip.height);
+
+    let mut image: Option<DecodedImage> = None;
+    for (index, (column, row)) in positions.into_iter().enumerate() {
+        let surface = decode(texture, &mip.surface(index as u32), options)?;
+        image
+            .get_or_insert_with(|| surface.blank(width, height))
+            .paste(
+                width,
+                &surface,
+                mip.width,
+                column * mip.width,
+                row * mip.height,
+            );
+    }
+
+    let image = image.ok_or_else(|| Error::new(ErrorKind::InvalidData, "mip has no surfaces!"))?;
+    Ok((image, width, height))
+}
+
+// only the first face or slice of `mip` is decoded, see `decode_surfaces` for the rest
+pub fn decode(
+    texture: &Texture,
+    mip: &MipLevel,
+    options: &DecodeOptions,
+) -> std::io::Result<DecodedImage> {
+    let mip = &mip.surface(0);
+    let width = mip.width as usize;
+    let height = mip.height as usize;
+    let mut decompressed = vec![0u8; 4 * width * height];
+    let read_u16s = |contents: &[u8]| -> Vec<u16> {
+        contents
+            .chunks_exact(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect()
+    };
+
+    let format = match texture.format {
+        // DXGI_FORMAT_BC1_UNORM(_SRGB)
+        71 | 72 => Format::Bc1,
+        // DXGI_FORMAT_BC2_UNORM(_SRGB)
+        74 | 75 => Format::Bc2,
+        // DXGI_FORMAT_BC3_UNORM(_SRGB)
+        77 | 78 => Format::Bc3,
+        // DXGI_FORMAT_BC4_UNORM
+        80 => Format::Bc4,
+        // DXGI_FORMAT_BC5_UNORM
+        83 => Format::Bc5,
+
+        // DXGI_FORMAT_BC7_UNORM(_SRGB)
+        98 | 99 => {
+            decompress_bc7(mip.contents, width, height, &mut decompressed);
+            return Ok(DecodedImage::Rgba8(decompressed));
+        }
+        // DXGI_FORMAT_BC6H_UF16, DXGI_FORMAT_BC6H_SF16
+        95 | 96 => {
+            let rgb = decompress_bc6h(mip.contents, width, height, texture.format == 96);
+            for (pixel, rgba) in rgb.chunks_exact(3).zip(decompressed.chunks_exact_mut(4)) {
+                for (channel, value) in pixel.iter().enumerate() {
+                    rgba[channel] = tone_map(*value, options);
+                }
+                rgba[3] = 0xFF;
+            }
+            return Ok(DecodedImage::Rgba8(decompressed));
+        }
+
+        // DXGI_FORMAT_R8G8B8A8_UNORM
+        28 => return Ok(DecodedImage::Rgba8(mip.contents.to_vec())),
+        // DXGI_FORMAT_B8G8R8A8_UNORM
+        87 => {
+            let pixels = mip
+                .contents
+                .chunks_exact(4)
+                .flat_map(|bgra| [bgra[2], bgra[1], bgra[0], bgra[3]])
+                .collect();
+            return Ok(DecodedImage::Rgba8(pixels));
+        }
+        // DXGI_FORMAT_R8_UNORM
+        61 => return Ok(DecodedImage::Gray8(mip.contents.to_vec())),
+        // DXGI_FORMAT_R16_UNORM
+        56 => return Ok(DecodedImage::Gray16(read_u16s(mip.contents))),
+        // DXGI_FORMAT_R16G16_UNORM, with blue left empty as normal map tools expect
+        35 => {
+            let pixels = read_u16s(mip.contents)
+                .chunks_exact(2)
+                .flat_map(|rg| [rg[0], rg[1], 0])
+                .collect();
+            return Ok(DecodedImage::Rgb16(pixels));
+        }
+
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "unsupported texture format!",
+            ))
+        }
+    };
+
+    format.decompress(mip.contents, width, height, &mut decompressed);
+
+    Ok(DecodedImage::Rgba8(decompressed))
+}
+
+// maps a linear HDR value to an sRGB-encoded byte
+fn tone_map(value: f32, options: &DecodeOptions) -> u8 {
+    let value = (value * options.exposure).max(0.0);
+    let value = match options.tone_mapping {
+        ToneMapping::Clamp => value.min(1.0),
+        ToneMapping::Reinhard => value / (1.0 + value),
+    };
+
+    let encoded = if value <= 0.0031308 {
+        12.92 * value
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+#[test]
+fn raw_formats() {
+    let options = DecodeOptions::default();
+
+    let texture = Texture::from_mips(87, 1, 1, vec![vec![1, 2, 3, 4]]).unwrap();
+    match decode(&texture, &texture.largest_mip().unwrap(), &options).unwrap() {
+        DecodedImage::Rgba8(pixels) => assert_eq!(pixels, [3, 2, 1, 4]),
+        _ => panic!("B8G8R8A8 should decode to RGBA"),
+    }
+
+    let texture = Texture::from_mips(56, 2, 1, vec![vec![0x34, 0x12, 0xFF, 0xFF]]).unwrap();
+    let decoded = decode(&texture, &texture.largest_mip().unwrap(), &options).unwrap();
+    match &decoded {
+        DecodedImage::Gray16(pixels) => assert_eq!(pixels, &[0x1234, 0xFFFF]),
+        _ => panic!("R16 should decode to 16-bit grayscale"),
+    }
+    assert_eq!(decoded.into_rgba8()[4..], [0xFF, 0xFF, 0xFF, 0xFF]);
+}
+
+#[test]
+fn cube_cross() {
+    let mut texture = Texture::from_mips(61, 1, 1, vec![vec![0]]).unwrap();
+    texture.flags = 0x40;
+    texture.elements[0].contents = vec![1, 2, 3, 4, 5, 6];
+
+    let mip = texture.largest_mip().unwrap();
+    let (image, width, height) =
+        decode_surfaces(&texture, &mip, &DecodeOptions::default()).unwrap();
+    assert_eq!((width, height), (4, 3));
+    match image {
+        DecodedImage::Gray8(pixels) => assert_eq!(pixels, [0, 3, 0, 0, 2, 5, 1, 6, 0, 4, 0, 0]),
+        _ => panic!("R8 should decode to grayscale"),
+    }
+}
@@ -0,0 +1,145 @@
+use std::io::{Error, ErrorKind};
+
+use texpresso::{Format, Params};
+
+use crate::file_formats::texture::Texture;
+use crate::helpers::error::to_io_error;
+
+// 8-bit RGBA pixels, as read from an ordinary image
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    // averages each 2x2 block of pixels, clamping at the edges of odd-sized images
+    pub fn downsample(&self) -> RgbaImage {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let pixel = |x: u32, y: u32| {
+            let x = x.min(self.width - 1) as usize;
+            let y = y.min(self.height - 1) as usize;
+            let start = 4 * (y * self.width as usize + x);
+            &self.pixels[start..start + 4]
+        };
+
+        let mut pixels = Vec::with_capacity(4 * (width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                for channel in 0..4 {
+                    let sum: u32 = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                        .iter()
+                        .map(|(dx, dy)| pixel(2 * x + dx, 2 * y + dy)[channel] as u32)
+                        .sum();
+                    pixels.push(((sum + 2) / 4) as u8);
+                }
+            }
+        }
+
+        RgbaImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+fn get_compression_format(format: u32) -> std::io::Result<Format> {
+    match format {
+        // DXGI_FORMAT_BC1_UNORM(_SRGB)
+        71 | 72 => Ok(Format::Bc1),
+        // DXGI_FORMAT_BC2_UNORM(_SRGB)
+        74 | 75 => Ok(Format::Bc2),
+        // DXGI_FORMAT_BC3_UNORM(_SRGB)
+        77 | 78 => Ok(Format::Bc3),
+        // DXGI_FORMAT_BC4_UNORM
+        80 => Ok(Format::Bc4),
+        // DXGI_FORMAT_BC5_UNORM
+        83 => Ok(Format::Bc5),
+
+        // DXGI_FORMAT_BC6H_UF16, DXGI_FORMAT_BC6H_SF16, DXGI_FORMAT_BC7_UNORM(_SRGB)
+        95 | 96 | 98 | 99 => Err(Error::new(
+            ErrorKind::Unsupported,
+            format!(
+                "texture format {} is BC6H or BC7, which can't be compressed yet; pick BC1 to BC5 instead!",
+                format
+            ),
+        )),
+
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("can't compress to texture format {}!", format),
+        )),
+    }
+}
+
+// compresses `image` and a full chain of mips generated from it down to 1x1
+pub fn encode_texture(image: RgbaImage, format: u32) -> std::io::Result<Texture> {
+    let compression_format = get_compression_format(format)?;
+    let width: u16 = image.width.try_into().map_err(to_io_error)?;
+    let height: u16 = image.height.try_into().map_err(to_io_error)?;
+    if width == 0 || height == 0 {
+        return Err(Error::new(ErrorKind::InvalidInput, "image has no pixels!"));
+    }
+
+    let mut mips = Vec::new();
+    let mut level = image;
+    loop {
+        let (mip_width, mip_height) = (level.width as usize, level.height as usize);
+        let mut compressed = vec![0u8; compression_format.compressed_size(mip_width, mip_height)];
+        compression_format.compress(
+            &level.pixels,
+            mip_width,
+            mip_height,
+            Params::default(),
+            &mut compressed,
+        );
+        mips.push(compressed);
+
+        if level.width == 1 && level.height == 1 {
+            break;
+        }
+        level = level.downsample();
+    }
+
+    Texture::from_mips(format, width, height, mips)
+}
+
+#[test]
+fn encode_mip_chain() {
+    let image = RgbaImage {
+        width: 8,
+        height: 4,
+        pixels: [0xFF, 0, 0, 0xFF].repeat(32),
+    };
+
+    let texture = encode_texture(image, 71).unwrap();
+    assert_eq!(texture.mip_count, 4);
+    let levels: Vec<(u32, u32, usize)> = texture
+        .mip_levels()
+        .unwrap()
+        .iter()
+        .map(|level| (level.width, level.height, level.contents.len()))
+        .collect();
+    assert_eq!(levels, [(8, 4, 16), (4, 2, 8), (2, 1, 8), (1, 1, 8)]);
+
+    let decoded = super::decoder::decode(&texture, &texture.mip(1).unwrap(), &Default::default())
+        .unwrap()
+        .into_rgba8();
+    assert_eq!(&decoded[..4], &[0xFF, 0, 0, 0xFF]);
+}
+
+#[test]
+fn bptc_is_unsupported() {
+    for format in [95, 96, 98, 99] {
+        let image = RgbaImage {
+            width: 4,
+            height: 4,
+            pixels: vec![0; 64],
+        };
+        let err = encode_texture(image, format).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        assert!(err.to_string().contains("BC6H or BC7"));
+    }
+}
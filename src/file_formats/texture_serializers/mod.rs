@@ -1,4 +1,7 @@
+pub(crate) mod bptc;
 pub(crate) mod dds_file;
+pub(crate) mod decoder;
+pub(crate) mod encoder;
 pub(crate) mod png_file;
 pub(crate) mod texture_serializer;
 pub(crate) mod webp_file;
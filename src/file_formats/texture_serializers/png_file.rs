@@ -1,51 +1,111 @@
-use std::io::{Error, ErrorKind, Seek, Write};
+use std::io::{BufRead, Error, ErrorKind, Seek, Write};
 
-use png::{BitDepth, ColorType, Encoder};
-use texpresso::Format;
+use png::{BitDepth, ColorType, Decoder, Encoder, SrgbRenderingIntent, Transformations};
 
-use crate::file_formats::texture::Texture;
+use crate::helpers::error::to_io_error;
 
-use super::texture_serializer::TextureSerializerExt;
+use super::decoder::{ColorSpace, DecodedImage};
+use super::encoder::RgbaImage;
+use super::texture_serializer::ImageSerializerExt;
 
 pub struct PNGFile {}
 
-impl TextureSerializerExt for PNGFile {
-    fn serialize<R: Seek + Write>(output: &mut R, texture: &Texture) -> std::io::Result<()> {
-        let format = match texture.format {
-            // DXGI_FORMAT_BC1_UNORM
-            71 => Ok(Format::Bc1),
-            // DXGI_FORMAT_BC2_UNORM
-            74 => Ok(Format::Bc2),
-            // DXGI_FORMAT_BC3_UNORM
-            77 => Ok(Format::Bc3),
-            // DXGI_FORMAT_BC4_UNORM
-            80 => Ok(Format::Bc4),
-            // DXGI_FORMAT_BC5_UNORM
-            83 => Ok(Format::Bc5),
-
-            _ => Err(Error::new(
-                ErrorKind::InvalidData,
-                "unsupported texture format!",
-            )),
-        }?;
-
-        let width = texture.width as usize;
-        let height = texture.height as usize;
-        let mut decompressed = vec![0u8; 4 * width * height];
-        format.decompress(
-            &texture.elements[0].contents,
-            texture.width.into(),
-            texture.height.into(),
-            &mut decompressed,
-        );
-
-        let mut encoder = Encoder::new(output, texture.width.into(), texture.height.into());
-        encoder.set_color(ColorType::Rgba);
-        encoder.set_depth(BitDepth::Eight);
+impl PNGFile {
+    // reads any PNG as 8-bit RGBA
+    pub fn deserialize<R: BufRead + Seek>(input: &mut R) -> std::io::Result<RgbaImage> {
+        let mut decoder = Decoder::new(input);
+        decoder.set_transformations(Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(to_io_error)?;
+
+        let mut buffer = vec![0u8; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(to_io_error)?;
+        let buffer = &buffer[..info.buffer_size()];
+
+        let pixels = match info.color_type {
+            ColorType::Rgba => buffer.to_vec(),
+            ColorType::Rgb => buffer
+                .chunks_exact(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 0xFF])
+                .collect(),
+            ColorType::GrayscaleAlpha => buffer
+                .chunks_exact(2)
+                .flat_map(|la| [la[0], la[0], la[0], la[1]])
+                .collect(),
+            ColorType::Grayscale => buffer.iter().flat_map(|&l| [l, l, l, 0xFF]).collect(),
+            ColorType::Indexed => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "indexed PNG was not expanded!",
+                ))
+            }
+        };
+
+        Ok(RgbaImage {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+}
+
+impl ImageSerializerExt for PNGFile {
+    fn write_image<R: Seek + Write>(
+        output: &mut R,
+        image: DecodedImage,
+        width: u32,
+        height: u32,
+        color_space: ColorSpace,
+    ) -> std::io::Result<()> {
+        let (color_type, bit_depth, data) = match image {
+            DecodedImage::Rgba8(pixels) => (ColorType::Rgba, BitDepth::Eight, pixels),
+            DecodedImage::Gray8(pixels) => (ColorType::Grayscale, BitDepth::Eight, pixels),
+            // PNG stores 16-bit samples big-endian
+            DecodedImage::Gray16(pixels) => (
+                ColorType::Grayscale,
+                BitDepth::Sixteen,
+                pixels
+                    .iter()
+                    .flat_map(|value| value.to_be_bytes())
+                    .collect(),
+            ),
+            DecodedImage::Rgb16(pixels) => (
+                ColorType::Rgb,
+                BitDepth::Sixteen,
+                pixels
+                    .iter()
+                    .flat_map(|value| value.to_be_bytes())
+                    .collect(),
+            ),
+        };
+
+        let mut encoder = Encoder::new(output, width, height);
+        encoder.set_color(color_type);
+        encoder.set_depth(bit_depth);
+        // also writes the matching gAMA and cHRM chunks; linear data is left untagged
+        if color_space == ColorSpace::Srgb {
+            encoder.set_srgb(SrgbRenderingIntent::Perceptual);
+        }
         let mut writer = encoder.write_header()?;
 
-        writer.write_image_data(&decompressed)?;
+        writer.write_image_data(&data)?;
 
         Ok(())
     }
 }
+
+#[test]
+fn srgb_chunk() {
+    use super::decoder::DecodeOptions;
+    use crate::file_formats::texture::Texture;
+
+    let contains_srgb = |format| {
+        let texture = Texture::from_mips(format, 4, 4, vec![vec![0; 8]]).unwrap();
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        let mip = texture.largest_mip().unwrap();
+        PNGFile::serialize_mip(&mut bytes, &texture, &mip, &DecodeOptions::default()).unwrap();
+        bytes.get_ref().windows(4).any(|chunk| chunk == b"sRGB")
+    };
+
+    assert!(contains_srgb(72));
+    assert!(!contains_srgb(71));
+}
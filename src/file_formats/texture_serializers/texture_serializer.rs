@@ -1,10 +1,12 @@
 use std::{
     fs::File,
     io::{BufWriter, Cursor, Seek, Write},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
-use crate::file_formats::texture::Texture;
+use crate::file_formats::texture::{MipLevel, Texture};
+
+use super::decoder::{decode_surfaces, ColorSpace, DecodeOptions, DecodedImage};
 
 // of the three currently supported formats, DDS is the fastest since data isn't processed
 // webp is the slowest, but yields by far the smallest files
@@ -14,13 +16,7 @@ pub trait TextureSerializerExt {
     fn serialize<R: Seek + Write>(output: &mut R, texture: &Texture) -> std::io::Result<()>;
 
     fn serialize_to_path<P: AsRef<Path>>(path: &P, texture: &Texture) -> std::io::Result<()> {
-        let file = File::options()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(path)?;
-        let mut buf_writer = BufWriter::new(file);
-        Self::serialize(&mut buf_writer, texture)
+        Self::serialize(&mut create_writer(path)?, texture)
     }
     fn serialize_to_bytes(texture: &Texture) -> std::io::Result<Vec<u8>> {
         let mut bytes: Vec<u8> = Vec::new();
@@ -29,3 +25,129 @@ pub trait TextureSerializerExt {
         Ok(bytes)
     }
 }
+
+pub enum MipSelection {
+    Largest,
+    Level(u8),
+    All,
+}
+
+// how the faces of a cube map or the slices of a volume are written
+pub enum SurfaceLayout {
+    // one image per mip, see `decode_surfaces`
+    Combined,
+    // one image per face or slice, e.g. `map.face0.png` or `map.slice0.png`
+    Split,
+}
+
+// serializers that write one mip level as an ordinary image; serializing a whole texture writes its
+// largest available level
+pub trait ImageSerializerExt {
+    fn write_image<R: Seek + Write>(
+        output: &mut R,
+        image: DecodedImage,
+        width: u32,
+        height: u32,
+        color_space: ColorSpace,
+    ) -> std::io::Result<()>;
+
+    fn serialize_mip<R: Seek + Write>(
+        output: &mut R,
+        texture: &Texture,
+        mip: &MipLevel,
+        options: &DecodeOptions,
+    ) -> std::io::Result<()> {
+        let (image, width, height) = decode_surfaces(texture, mip, options)?;
+        Self::write_image(output, image, width, height, ColorSpace::of(texture))
+    }
+
+    // with `MipSelection::All`, each level is written alongside `path`, e.g. `map.mip1.png`
+    fn serialize_mips_to_path<P: AsRef<Path>>(
+        path: &P,
+        texture: &Texture,
+        selection: MipSelection,
+        options: &DecodeOptions,
+        layout: SurfaceLayout,
+    ) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let levels = match selection {
+            MipSelection::Largest => vec![(path.to_path_buf(), texture.largest_mip()?)],
+            MipSelection::Level(index) => vec![(path.to_path_buf(), texture.mip(index)?)],
+            MipSelection::All => texture
+                .mip_levels()?
+                .into_iter()
+                .map(|level| (mip_path(path, level.index), level))
+                .collect(),
+        };
+
+        for (path, level) in levels {
+            match layout {
+                SurfaceLayout::Split if level.surfaces > 1 => {
+                    let name = if texture.is_cube_map() {
+                        "face"
+                    } else {
+                        "slice"
+                    };
+                    for index in 0..level.surfaces {
+                        let surface_path = suffixed_path(&path, &format!("{}{}", name, index));
+                        let surface = level.surface(index);
+                        Self::serialize_mip(
+                            &mut create_writer(&surface_path)?,
+                            texture,
+                            &surface,
+                            options,
+                        )?;
+                    }
+                }
+                _ => Self::serialize_mip(&mut create_writer(&path)?, texture, &level, options)?,
+            }
+        }
+
+        write_sidecar(path, texture)
+    }
+}
+
+impl<T: ImageSerializerExt> TextureSerializerExt for T {
+    fn serialize<R: Seek + Write>(output: &mut R, texture: &Texture) -> std::io::Result<()> {
+        T::serialize_mip(
+            output,
+            texture,
+            &texture.largest_mip()?,
+            &DecodeOptions::default(),
+        )
+    }
+}
+
+// records what the image files alone can't, e.g. `map.png.json` for `map.png`
+fn write_sidecar(path: &Path, texture: &Texture) -> std::io::Result<()> {
+    let mut sidecar_path = path.as_os_str().to_owned();
+    sidecar_path.push(".json");
+
+    let mut writer = create_writer(&sidecar_path)?;
+    writeln!(
+        writer,
+        "{{\"format\": {}, \"color_space\": \"{}\"}}",
+        texture.format,
+        ColorSpace::of(texture).name()
+    )?;
+    writer.flush()
+}
+
+fn mip_path(path: &Path, index: u8) -> PathBuf {
+    suffixed_path(path, &format!("mip{}", index))
+}
+
+// puts `suffix` before the extension, e.g. `map.png` becomes `map.<suffix>.png`
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    path.with_extension(format!("{}.{}", suffix, extension))
+}
+
+fn create_writer<P: AsRef<Path>>(path: &P) -> std::io::Result<BufWriter<File>> {
+    let file = File::options()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)?;
+    Ok(BufWriter::new(file))
+}
@@ -1,52 +1,54 @@
-use std::io::{Error, ErrorKind, Seek, Write};
-use texpresso::Format;
-use webp::{Encoder, PixelLayout};
+use std::io::{Error, ErrorKind, Read, Seek, Write};
+use webp::{Decoder, Encoder, PixelLayout};
 
-use crate::file_formats::texture::Texture;
-
-use super::texture_serializer::TextureSerializerExt;
+use super::decoder::{ColorSpace, DecodedImage};
+use super::encoder::RgbaImage;
+use super::texture_serializer::ImageSerializerExt;
 
 pub struct WEBPFile {}
 
-impl TextureSerializerExt for WEBPFile {
-    fn serialize<R: Seek + Write>(output: &mut R, texture: &Texture) -> std::io::Result<()> {
-        let format = match texture.format {
-            // DXGI_FORMAT_BC1_UNORM
-            71 => Ok(Format::Bc1),
-            // DXGI_FORMAT_BC2_UNORM
-            74 => Ok(Format::Bc2),
-            // DXGI_FORMAT_BC3_UNORM
-            77 => Ok(Format::Bc3),
-            // DXGI_FORMAT_BC4_UNORM
-            80 => Ok(Format::Bc4),
-            // DXGI_FORMAT_BC5_UNORM
-            83 => Ok(Format::Bc5),
-
-            _ => Err(Error::new(
-                ErrorKind::InvalidData,
-                "unsupported texture format!",
-            )),
-        }?;
-
-        let width = texture.width as usize;
-        let height = texture.height as usize;
-        let mut decompressed = vec![0u8; 4 * width * height];
-        format.decompress(
-            &texture.elements[0].contents,
-            texture.width.into(),
-            texture.height.into(),
-            &mut decompressed,
-        );
-
-        let encoded = Encoder::new(
-            &decompressed,
-            PixelLayout::Rgba,
-            texture.width.into(),
-            texture.height.into(),
-        )
-        .encode_lossless();
-
-        output.write(&encoded)?;
+impl WEBPFile {
+    // reads a still WebP as 8-bit RGBA
+    pub fn deserialize<R: Read + Seek>(input: &mut R) -> std::io::Result<RgbaImage> {
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+
+        let image = Decoder::new(&bytes)
+            .decode()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "could not decode WebP!"))?;
+
+        let pixels = if image.is_alpha() {
+            image.to_vec()
+        } else {
+            image
+                .chunks_exact(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 0xFF])
+                .collect()
+        };
+
+        Ok(RgbaImage {
+            width: image.width(),
+            height: image.height(),
+            pixels,
+        })
+    }
+}
+
+impl ImageSerializerExt for WEBPFile {
+    // WebP has no way to mark a colour space, so that's left to the sidecar
+    fn write_image<R: Seek + Write>(
+        output: &mut R,
+        image: DecodedImage,
+        width: u32,
+        height: u32,
+        _color_space: ColorSpace,
+    ) -> std::io::Result<()> {
+        let decompressed = image.into_rgba8();
+
+        let encoded =
+            Encoder::new(&decompressed, PixelLayout::Rgba, width, height).encode_lossless();
+
+        output.write_all(&encoded)?;
 
         Ok(())
     }
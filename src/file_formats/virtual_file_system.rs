@@ -0,0 +1,168 @@
+use std::cmp::Reverse;
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::util::jenkins::hash_string;
+
+use super::archive_index::ArchiveIndex;
+use super::archive_table::ArchiveTableEntry;
+use super::file_lists::FileLists;
+
+// resolves paths the way the game does: patch archives override DLC archives, which override the
+// base archives, and within a directory higher-numbered (newer) archives override lower ones.
+// this follows how the game is updated rather than a reading of its loader: `patch_win64` only ever
+// receives newer copies of files that already exist in the other two directories, DLC archives
+// replace base game files they change, and each update adds the next `gameN` archive instead of
+// rewriting the old ones, so the copy in the highest-numbered archive is the newest
+
+pub struct VirtualFileSystem {
+    pub archive_index: ArchiveIndex,
+    names: Vec<String>,
+}
+
+pub struct Resolution<'a> {
+    pub archive: &'a str,
+    pub entry: &'a ArchiveTableEntry,
+}
+
+impl VirtualFileSystem {
+    pub fn from_game_dir<P: AsRef<Path>>(
+        file_lists: &FileLists,
+        game_dir: &P,
+    ) -> std::io::Result<VirtualFileSystem> {
+        let mut archive_names = ArchiveIndex::find_archives(game_dir)?;
+        archive_names.sort_by_cached_key(|name| VirtualFileSystem::precedence(name));
+
+        let mut archive_index = ArchiveIndex::new();
+        for name in &archive_names {
+            archive_index.add_archive(game_dir, name)?;
+        }
+
+        // several archives list the same names, and the file lists also name files no archive has
+        let mut names: Vec<String> = file_lists
+            .values()
+            .flatten()
+            .filter(|entry| archive_index.contains(entry.name_hash))
+            .map(|entry| entry.name.to_owned())
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+
+        Ok(VirtualFileSystem {
+            archive_index,
+            names,
+        })
+    }
+
+    // sorts archives into override order, e.g. `patch_win64/game1` comes before `patch_win64/game0`
+    fn precedence(archive_name: &str) -> (u8, Reverse<u32>, String) {
+        let directory = archive_name.split('/').next().unwrap_or_default();
+        let file_name = archive_name.rsplit('/').next().unwrap_or_default();
+
+        let layer = match directory {
+            "patch_win64" => 0,
+            "dlc_win64" | "dlc" => 1,
+            "archives_win64" => 2,
+            _ => 3,
+        };
+
+        let number = file_name
+            .trim_start_matches(|c: char| !c.is_ascii_digit())
+            .parse()
+            .unwrap_or(0);
+
+        (layer, Reverse(number), archive_name.to_string())
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.archive_index.contains(hash_string(path))
+    }
+
+    pub fn open(&self, path: &str) -> std::io::Result<Cursor<&[u8]>> {
+        self.archive_index.open_by_name(path)
+    }
+
+    // every known name starting with `prefix`, sorted
+    pub fn list(&self, prefix: &str) -> Vec<&str> {
+        let start = self.names.partition_point(|name| name.as_str() < prefix);
+        self.names[start..]
+            .iter()
+            .take_while(|name| name.starts_with(prefix))
+            .map(|name| name.as_str())
+            .collect()
+    }
+
+    // the entry that wins first, followed by the ones it shadows
+    pub fn which_archive(&self, path: &str) -> Vec<Resolution<'_>> {
+        self.archive_index
+            .locate_all(hash_string(path))
+            .iter()
+            .map(|location| {
+                let archive = &self.archive_index.archives[location.archive];
+                Resolution {
+                    archive: &archive.name,
+                    entry: &archive.archive_table.entries[location.entry],
+                }
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn precedence() {
+    let mut archive_names = vec![
+        "archives_win64/game0",
+        "archives_win64/game1",
+        "dlc_win64/mech_land/game0",
+        "patch_win64/game0",
+        "patch_win64/game9",
+        "patch_win64/game10",
+        "tools/game5",
+    ];
+    archive_names.reverse();
+    archive_names.sort_by_cached_key(|name| VirtualFileSystem::precedence(name));
+
+    assert_eq!(
+        archive_names,
+        [
+            // numbers are compared as numbers, so `game10` wins over `game9`
+            "patch_win64/game10",
+            "patch_win64/game9",
+            "patch_win64/game0",
+            "dlc_win64/mech_land/game0",
+            "archives_win64/game1",
+            "archives_win64/game0",
+            // anything outside the known directories loses to all of them
+            "tools/game5",
+        ]
+    );
+}
+
+#[test]
+fn precedence_ties() {
+    // archives with the same layer and number fall back to name order, so the result is stable
+    let mut archive_names = vec!["dlc_win64/b/game0", "dlc/a/game0"];
+    archive_names.sort_by_cached_key(|name| VirtualFileSystem::precedence(name));
+    assert_eq!(archive_names, ["dlc/a/game0", "dlc_win64/b/game0"]);
+}
+
+#[test]
+fn exists() {
+    use super::file_lists::FileLists;
+    use super::packed_archive::{PackedArchive, PackedArchiveEntry};
+
+    let game_dir = std::env::temp_dir().join("jc3-tools-vfs-exists");
+    let _ = std::fs::remove_dir_all(&game_dir);
+    std::fs::create_dir_all(game_dir.join("archives_win64")).unwrap();
+    let entries = vec![PackedArchiveEntry {
+        name: "ui/intro.gfx".to_string(),
+        contents: vec![1, 2, 3],
+    }];
+    PackedArchive::serialize_to_path(&game_dir.join("archives_win64/game0"), &entries, true)
+        .unwrap();
+
+    let virtual_file_system =
+        VirtualFileSystem::from_game_dir(&FileLists::new(), &game_dir).unwrap();
+    assert!(virtual_file_system.exists("ui/intro.gfx"));
+    assert!(!virtual_file_system.exists("ui/outro.gfx"));
+}
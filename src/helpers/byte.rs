@@ -20,6 +20,10 @@ impl<T: Seek> StreamLengthExt for T {
     }
 }
 
+pub fn align(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
 pub trait ByteReaderExt: std::io::Read {
     fn read_bytes<const N: usize>(&mut self) -> std::io::Result<[u8; N]>;
 
@@ -106,6 +110,20 @@ pub trait ByteReaderExt: std::io::Read {
 pub trait ByteWriterExt {
     fn write_bytes<const N: usize>(&mut self, bytes: [u8; N]) -> std::io::Result<()>;
 
+    fn write_signature<const N: usize>(
+        &mut self,
+        le_signature: &[u8; N],
+        le: bool,
+    ) -> std::io::Result<()> {
+        let mut magic = *le_signature;
+
+        if !le {
+            magic.reverse();
+        }
+
+        self.write_bytes(magic)
+    }
+
     fn write_u8(&mut self, value: u8) -> std::io::Result<()> {
         let bytes = value.to_ne_bytes();
         self.write_bytes(bytes)?;
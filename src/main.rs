@@ -2,40 +2,500 @@ mod file_formats;
 mod helpers;
 mod util;
 
-use std::fs::create_dir_all;
+use std::collections::HashMap;
+use std::env::args;
+use std::fs::{create_dir_all, metadata, read, write, File};
+use std::io::{copy, BufReader, BufWriter, Error, ErrorKind, Write};
 use std::path::Path;
 
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+
 use file_formats::texture::Texture;
-use file_formats::texture_serializers::texture_serializer::TextureSerializerExt;
+use file_formats::texture_serializers::texture_serializer::{
+    ImageSerializerExt, MipSelection, SurfaceLayout, TextureSerializerExt,
+};
 use helpers::serializable::SerializableExt;
 
-use crate::file_formats::cool_archive::CoolArchive;
-use crate::file_formats::file_lists::load_with_filter;
-use crate::file_formats::packed_archive::PackedArchive;
+use crate::file_formats::archive_diff::{self, EntryLocation};
+use crate::file_formats::archive_index::ArchiveIndex;
+use crate::file_formats::archive_table::ArchiveTable;
+use crate::file_formats::cool_archive::{AafReader, CoolArchive, DEFAULT_BLOCK_SIZE};
+use crate::file_formats::file_lists::{find_collisions, load, load_with_filter};
+use crate::file_formats::packed_archive::{PackedArchive, ReplaceMode};
+use crate::file_formats::small_archive::SmallArchive;
+use crate::file_formats::texture_serializers::dds_file::DDSFile;
+use crate::file_formats::texture_serializers::decoder::{DecodeOptions, ToneMapping};
+use crate::file_formats::texture_serializers::encoder::encode_texture;
+use crate::file_formats::texture_serializers::png_file::PNGFile;
 use crate::file_formats::texture_serializers::webp_file::WEBPFile;
+use crate::file_formats::virtual_file_system::VirtualFileSystem;
+use crate::helpers::error::to_io_error;
 
 fn extract_map() -> std::io::Result<()> {
-    let file_list_entries = load_with_filter(|name| {
+    let file_lists = load_with_filter(|name| {
         name.starts_with("textures/ui/zoom")
             || name.starts_with("textures/ui/world_map")
             || name.starts_with("textures/ui/dev_map_grid")
     })?;
 
-    let packed_archive_entries =
-        PackedArchive::deserialize_from_file_lists(file_list_entries, &"game_dir")?;
+    let virtual_file_system = VirtualFileSystem::from_game_dir(&file_lists, &"game_dir")?;
+
+    let names: Vec<&str> = virtual_file_system
+        .list("textures/ui/")
+        .into_iter()
+        .filter(|name| name.ends_with(".ddsc"))
+        .collect();
+    names
+        .par_iter()
+        .map(|name| {
+            let path = Path::new(name);
+            let dir_path = path.with_extension("");
+            create_dir_all(dir_path.parent().unwrap())?;
+            let texture = Texture::load(&virtual_file_system, name)?;
+            WEBPFile::serialize_to_path(&path.with_extension("webp"), &texture)
+        })
+        .collect::<std::io::Result<()>>()?;
+
+    for name in names {
+        println!("{}", name);
+    }
+
+    Ok(())
+}
+
+fn load_names() -> std::io::Result<HashMap<u32, String>> {
+    let names = load()?
+        .into_values()
+        .flatten()
+        .map(|entry| (entry.name_hash, entry.name))
+        .collect();
+    Ok(names)
+}
+
+// each file is written once, from the archive `which` reports it in first, so the shadowed copies
+// of patched files are skipped
+fn extract(game_dir: &str, output_dir: &str) -> std::io::Result<()> {
+    let names = load_names()?;
+    extract_with_names(game_dir, output_dir, &names)
+}
+
+fn extract_with_names(
+    game_dir: &str,
+    output_dir: &str,
+    names: &HashMap<u32, String>,
+) -> std::io::Result<()> {
+    let virtual_file_system = VirtualFileSystem::from_game_dir(&HashMap::new(), &game_dir)?;
+    let archive_index = &virtual_file_system.archive_index;
+
+    let name_hashes: Vec<u32> = archive_index.name_hashes().collect();
+    name_hashes
+        .par_iter()
+        .map(|&name_hash| {
+            let contents = archive_index.get(name_hash)?;
+            let name = match names.get(&name_hash) {
+                Some(name) => name.to_owned(),
+                None => PackedArchive::unknown_entry_name(name_hash, contents)?,
+            };
+
+            let path = Path::new(output_dir).join(name);
+            create_dir_all(path.parent().unwrap())?;
+            write(path, contents)
+        })
+        .collect()
+}
+
+fn which(game_dir: &str, path: &str) -> std::io::Result<()> {
+    let virtual_file_system = VirtualFileSystem::from_game_dir(&HashMap::new(), &game_dir)?;
+
+    if !virtual_file_system.exists(path) {
+        println!("{} not found", path);
+    }
+
+    for (i, resolution) in virtual_file_system.which_archive(path).iter().enumerate() {
+        println!(
+            "{} {} at {:#X}, {} bytes",
+            if i == 0 { "found in" } else { "shadowed in" },
+            resolution.archive,
+            resolution.entry.offset,
+            resolution.entry.size
+        );
+    }
+
+    Ok(())
+}
+
+fn verify_archive(game_dir: &str, name: &str) -> std::io::Result<usize> {
+    let base_path = Path::new(game_dir).join(name);
+    let archive_table = ArchiveTable::deserialize_from_path(&base_path.with_extension("tab"))?;
+    let archive_length = metadata(base_path.with_extension("arc"))?.len();
+
+    let issues = archive_table.verify(archive_length.try_into().map_err(to_io_error)?);
+    for issue in &issues {
+        println!("{}: {}", name, issue);
+    }
+
+    Ok(issues.len())
+}
+
+fn verify(game_dir: &str) -> std::io::Result<()> {
+    let mut issue_count = 0;
+
+    for name in ArchiveIndex::find_archives(&game_dir)? {
+        match verify_archive(game_dir, &name) {
+            Ok(count) => issue_count += count,
+            Err(err) => {
+                println!("{}: {}", name, err);
+                issue_count += 1;
+            }
+        }
+    }
+
+    for (name_hash, names) in find_collisions(&load()?) {
+        println!(
+            "file_lists: {:08X} is shared by {}",
+            name_hash,
+            names.join(", ")
+        );
+        issue_count += 1;
+    }
+
+    println!("found {} issues", issue_count);
+
+    Ok(())
+}
+
+// a game directory is resolved like the game would, anything else is read as a single TAB/ARC pair
+fn open_for_diff(path: &str) -> std::io::Result<ArchiveIndex> {
+    let path = Path::new(path);
+    if path.is_dir() {
+        let virtual_file_system = VirtualFileSystem::from_game_dir(&HashMap::new(), &path)?;
+        return Ok(virtual_file_system.archive_index);
+    }
+
+    let name = path.with_extension("");
+    let name = name.file_name().unwrap_or_default().to_string_lossy();
+    let mut archive_index = ArchiveIndex::new();
+    archive_index.add_archive(&path.parent().unwrap_or(Path::new("")), &name)?;
+    Ok(archive_index)
+}
+
+fn diff(old_path: &str, new_path: &str) -> std::io::Result<()> {
+    let names = load_names()?;
+    let old = open_for_diff(old_path)?;
+    let new = open_for_diff(new_path)?;
+
+    let mut file_type = "";
+    for change in archive_diff::diff(&old, &new)? {
+        if change.file_type != file_type {
+            file_type = change.file_type;
+            println!("{}:", file_type);
+        }
+
+        let name = match names.get(&change.name_hash) {
+            Some(name) => name.to_owned(),
+            None => format!("{:08X}", change.name_hash),
+        };
+        let describe = |location: &Option<EntryLocation>| match location {
+            Some(location) => format!(
+                "{} at {:#X}, {} bytes",
+                location.archive, location.offset, location.size
+            ),
+            None => "nowhere".to_string(),
+        };
+        println!(
+            "  {} {} ({} -> {})",
+            change.kind,
+            name,
+            describe(&change.old),
+            describe(&change.new)
+        );
+    }
+
+    Ok(())
+}
+
+fn compress(path: &str, output_path: &str) -> std::io::Result<()> {
+    let contents = read(path)?;
+    let cool_archive = CoolArchive::from_contents(&contents, DEFAULT_BLOCK_SIZE)?;
+    cool_archive.serialize_to_path(&output_path)
+}
+
+// checks every chunk's sizes up front, rather than finding out from a failed inflate
+fn decompress_strict(path: &str, output_path: &str) -> std::io::Result<()> {
+    let cool_archive = CoolArchive::deserialize_strict(&mut BufReader::new(File::open(path)?))?;
+    let mut writer = BufWriter::new(File::create(output_path)?);
+    for chunk in &cool_archive.chunks {
+        writer.write_all(&chunk.contents)?;
+    }
+    writer.flush()
+}
+
+fn decompress(path: &str, output_path: &str) -> std::io::Result<()> {
+    let mut reader = AafReader::new(BufReader::new(File::open(path)?))?;
+    let mut writer = BufWriter::new(File::create(output_path)?);
+    copy(&mut reader, &mut writer)?;
+    writer.flush()
+}
+
+// reads a file that may or may not be wrapped in an AAF container, along with the container if it was
+fn read_unwrapped(path: &str) -> std::io::Result<(Vec<u8>, Option<CoolArchive>)> {
+    let contents = read(path)?;
+    if !contents.starts_with(b"AAF\0") {
+        return Ok((contents, None));
+    }
+
+    let cool_archive = CoolArchive::deserialize_from_bytes(&contents)?;
+    let unwrapped = cool_archive
+        .chunks
+        .iter()
+        .flat_map(|chunk| chunk.contents.iter().copied())
+        .collect();
+    Ok((unwrapped, Some(cool_archive)))
+}
+
+fn list_small_archive(path: &str, output_dir: Option<&str>) -> std::io::Result<()> {
+    let (contents, _) = read_unwrapped(path)?;
+    let small_archive = SmallArchive::deserialize_from_bytes(&contents)?;
+
+    for entry in &small_archive.entries {
+        if entry.is_external() {
+            println!("{} (external, {} bytes)", entry.name, entry.size);
+        } else {
+            println!(
+                "{} at {:#X}, {} bytes",
+                entry.name, entry.offset, entry.size
+            );
+        }
+    }
+
+    if let Some(output_dir) = output_dir {
+        for entry in small_archive.into_packed_archive_entries() {
+            let path = Path::new(output_dir).join(&entry.name);
+            create_dir_all(path.parent().unwrap())?;
+            write(path, &entry.contents)?;
+        }
+    }
+
+    Ok(())
+}
+
+// swaps in any inline entries found in `directory` and writes the result like the original
+fn repack_small_archive(path: &str, directory: &str, output_path: &str) -> std::io::Result<()> {
+    let (contents, wrapper) = read_unwrapped(path)?;
+    let mut small_archive = SmallArchive::deserialize_from_bytes(&contents)?;
+
+    for entry in &mut small_archive.entries {
+        let entry_path = Path::new(directory).join(&entry.name);
+        if !entry.is_external() && entry_path.is_file() {
+            println!("{}", entry.name);
+            entry.contents = read(entry_path)?;
+        }
+    }
+
+    match wrapper {
+        // rewrapped with the original's block size and byte order
+        Some(original) => {
+            let contents = small_archive.serialize_to_bytes()?;
+            let mut cool_archive = CoolArchive::from_contents(&contents, original.block_size)?;
+            cool_archive.le = original.le;
+            cool_archive.serialize_to_path(&output_path)
+        }
+        None => small_archive.serialize_to_path(&output_path),
+    }
+}
+
+// reads a `.ddsc` from disk, along with the `.hmddsc` next to it if it has one
+fn read_texture(path: &str) -> std::io::Result<Texture> {
+    let mut texture = Texture::deserialize_from_path(&path)?;
+
+    if texture.has_external_elements() {
+        if let Some(high_mip_path) = Texture::high_mip_name(path) {
+            if Path::new(&high_mip_path).is_file() {
+                texture.read_external_elements(&mut BufReader::new(File::open(high_mip_path)?))?;
+            }
+        }
+    }
+
+    Ok(texture)
+}
+
+// `convert [--mip <index> | --all-mips] [--split] [--tone-map clamp|reinhard] [--exposure <value>] <file> <output>`
+fn convert(args: &[&str]) -> std::io::Result<()> {
+    let usage = || {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "usage: convert [--mip <index> | --all-mips] [--split] [--tone-map clamp|reinhard] [--exposure <value>] <file> <output>",
+        )
+    };
+
+    let mut selection = MipSelection::Largest;
+    let mut options = DecodeOptions::default();
+    let mut layout = SurfaceLayout::Combined;
+    let mut args = args;
+    loop {
+        match args {
+            ["--mip", index, rest @ ..] => {
+                selection = MipSelection::Level(index.parse().map_err(to_io_error)?);
+                args = rest;
+            }
+            ["--all-mips", rest @ ..] => {
+                selection = MipSelection::All;
+                args = rest;
+            }
+            ["--split", rest @ ..] => {
+                layout = SurfaceLayout::Split;
+                args = rest;
+            }
+            ["--tone-map", tone_mapping, rest @ ..] => {
+                options.tone_mapping = match *tone_mapping {
+                    "clamp" => ToneMapping::Clamp,
+                    "reinhard" => ToneMapping::Reinhard,
+                    _ => return Err(usage()),
+                };
+                args = rest;
+            }
+            ["--exposure", exposure, rest @ ..] => {
+                options.exposure = exposure.parse().map_err(to_io_error)?;
+                args = rest;
+            }
+            _ => break,
+        }
+    }
+    let [path, output_path] = args else {
+        return Err(usage());
+    };
+
+    let texture = read_texture(path)?;
+
+    match Path::new(output_path).extension().and_then(|e| e.to_str()) {
+        Some("png") => {
+            PNGFile::serialize_mips_to_path(output_path, &texture, selection, &options, layout)
+        }
+        Some("webp") => {
+            WEBPFile::serialize_mips_to_path(output_path, &texture, selection, &options, layout)
+        }
+        // DDS files always hold the whole mip chain
+        Some("dds") => DDSFile::serialize_to_path(output_path, &texture),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "output must be a .png, .webp or .dds file!",
+        )),
+    }
+}
+
+// `import [--like <original>] [--format <dxgi>] <file> <output>`
+fn import(args: &[&str]) -> std::io::Result<()> {
+    let usage = || {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "usage: import [--like <original>] [--format <dxgi>] <file> <output>",
+        )
+    };
+
+    let mut original = None;
+    let mut format = None;
+    let mut args = args;
+    loop {
+        match args {
+            ["--like", original_path, rest @ ..] => {
+                original = Some(read_texture(original_path)?);
+                args = rest;
+            }
+            ["--format", dxgi_format, rest @ ..] => {
+                format = Some(dxgi_format.parse::<u32>().map_err(to_io_error)?);
+                args = rest;
+            }
+            _ => break,
+        }
+    }
+    let [path, output_path] = args else {
+        return Err(usage());
+    };
+
+    // images are compressed to the chosen format, or the format of the texture being replaced
+    let format = format.or(original.as_ref().map(|original| original.format));
+    let mut texture = match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("dds") => DDSFile::deserialize_from_path(path)?,
+        Some("png") => encode_texture(
+            PNGFile::deserialize(&mut BufReader::new(File::open(path)?))?,
+            format.ok_or_else(usage)?,
+        )?,
+        Some("webp") => encode_texture(
+            WEBPFile::deserialize(&mut BufReader::new(File::open(path)?))?,
+            format.ok_or_else(usage)?,
+        )?,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "input must be a .dds, .png or .webp file!",
+            ))
+        }
+    };
+
+    if let Some(original) = &original {
+        texture.copy_header_from(original)?;
+    }
 
-    for entry in packed_archive_entries {
-        let path = Path::new(&entry.name);
-        let dir_path = path.with_extension("");
-        create_dir_all(dir_path.parent().unwrap())?;
-        println!("{}", entry.name);
-        let texture = Texture::deserialize_from_bytes(&entry.contents)?;
-        WEBPFile::serialize_to_path(&path.with_extension("webp"), &texture)?;
+    texture.serialize_to_path(output_path)
+}
+
+fn texture_info(path: &str) -> std::io::Result<()> {
+    let texture = read_texture(path)?;
+
+    println!(
+        "format {}, {}x{}x{}, dimension {}, flags {:#X}",
+        texture.format,
+        texture.width,
+        texture.height,
+        texture.depth,
+        texture.dimension,
+        texture.flags
+    );
+    println!(
+        "{} mips ({} in header), category {:?}, usage {:?}",
+        texture.mip_count,
+        texture.header_mip_count,
+        texture.category(),
+        texture.usage()
+    );
+    println!(
+        "unknown_06 {:#04X}, unknown_1c {:#010X}",
+        texture.unknown_06, texture.unknown_1c
+    );
+
+    for (i, element) in texture.elements.iter().enumerate() {
+        if element.size == 0 {
+            continue;
+        }
+
+        let location = if element.is_external {
+            "external"
+        } else {
+            "inline"
+        };
+        println!(
+            "  element {}: {} at {:#X}, {} bytes",
+            i, location, element.offset, element.size
+        );
     }
 
     Ok(())
 }
 
+fn pack(directory: &str, archive: &str) -> std::io::Result<()> {
+    let entries = PackedArchive::entries_from_directory(&directory)?;
+    let archive_table = PackedArchive::serialize_to_path(&archive, &entries, true)?;
+    println!("packed {} entries", archive_table.entries.len());
+
+    Ok(())
+}
+
+fn replace(archive: &str, name: &str, path: &str, mode: ReplaceMode) -> std::io::Result<()> {
+    let contents = read(path)?;
+    PackedArchive::replace_entry(&archive, name, &contents, mode)
+}
+
 fn main() -> std::io::Result<()> {
     // let file_list_entries = load_with_filter(|name| name.ends_with("l"))?;
 
@@ -50,7 +510,78 @@ fn main() -> std::io::Result<()> {
     //     }
     // }
 
-    extract_map()?;
+    let args: Vec<String> = args().collect();
+    let mut args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
 
-    Ok(())
+    // e.g. `--threads 4 extract ...`, otherwise rayon uses one thread per core
+    if let ["--threads", thread_count, ..] = args[1..] {
+        ThreadPoolBuilder::new()
+            .num_threads(thread_count.parse().map_err(to_io_error)?)
+            .build_global()
+            .map_err(to_io_error)?;
+        args.drain(1..3);
+    }
+
+    match args[1..] {
+        [] => extract_map(),
+        ["extract", game_dir, output_dir] => extract(game_dir, output_dir),
+        ["which", game_dir, path] => which(game_dir, path),
+        ["verify", game_dir] => verify(game_dir),
+        ["diff", old_path, new_path] => diff(old_path, new_path),
+        ["compress", path, output_path] => compress(path, output_path),
+        ["decompress", path, output_path] => decompress(path, output_path),
+        ["decompress", "--strict", path, output_path] => decompress_strict(path, output_path),
+        ["sarc", path] => list_small_archive(path, None),
+        ["sarc", path, output_dir] => list_small_archive(path, Some(output_dir)),
+        ["sarc-repack", path, directory, output_path] => {
+            repack_small_archive(path, directory, output_path)
+        }
+        ["texture", path] => texture_info(path),
+        ["import", ref rest @ ..] => import(rest),
+        ["convert", ref rest @ ..] => convert(rest),
+        ["pack", directory, archive] => pack(directory, archive),
+        ["replace", archive, name, path] => replace(archive, name, path, ReplaceMode::Backup),
+        ["replace", "--no-backup", archive, name, path] => {
+            replace(archive, name, path, ReplaceMode::Overwrite)
+        }
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "usage: jc3-tools [--threads <count>] [extract <game_dir> <output_dir> | which <game_dir> <path> | verify <game_dir> | diff <old> <new> | compress <file> <output> | decompress [--strict] <file> <output> | sarc <file> [<output_dir>] | sarc-repack <file> <directory> <output> | texture <file> | import [--like <original>] [--format <dxgi>] <file> <output> | convert [--mip <index> | --all-mips] [--split] [--tone-map clamp|reinhard] [--exposure <value>] <file> <output> | pack <directory> <archive> | replace [--no-backup] <archive> <name> <file>]",
+        )),
+    }
+}
+
+#[test]
+fn extract_writes_winning_copy() {
+    use crate::file_formats::packed_archive::PackedArchiveEntry;
+    use crate::util::jenkins::hash_string;
+
+    let directory = std::env::temp_dir().join("jc3-tools-extract");
+    let _ = std::fs::remove_dir_all(&directory);
+    let game_dir = directory.join("game_dir");
+    let output_dir = directory.join("output");
+
+    // `game9` sorts after `game10` by path, but the higher number still wins
+    for (archive, contents) in [
+        ("archives_win64/game0", "base"),
+        ("patch_win64/game10", "newest"),
+        ("patch_win64/game9", "older"),
+    ] {
+        let archive_path = game_dir.join(archive);
+        create_dir_all(archive_path.parent().unwrap()).unwrap();
+        let entries = vec![PackedArchiveEntry {
+            name: "a.txt".to_string(),
+            contents: contents.as_bytes().to_vec(),
+        }];
+        PackedArchive::serialize_to_path(&archive_path, &entries, true).unwrap();
+    }
+
+    let names = HashMap::from([(hash_string("a.txt"), "a.txt".to_string())]);
+    extract_with_names(
+        &game_dir.to_string_lossy(),
+        &output_dir.to_string_lossy(),
+        &names,
+    )
+    .unwrap();
+    assert_eq!(read(output_dir.join("a.txt")).unwrap(), b"newest");
 }